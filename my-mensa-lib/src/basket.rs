@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use crate::{Meal, MensaError, MenuItem, Result};
+
+/// Most portions of one meal in a [`Basket`].
+pub const MAX_QUANTITY: u32 = 10;
+
+/// A meal in a [`Basket`], with the number of portions to order.
+#[derive(Clone, Debug)]
+pub struct BasketItem {
+    pub item: MenuItem,
+    pub quantity: u32,
+}
+
+/// Several meals of one day, submitted as a single order with one pickup slot.
+#[derive(Clone, Debug)]
+pub struct Basket {
+    date: NaiveDate,
+    items: Vec<BasketItem>,
+}
+
+impl Basket {
+    pub fn new(date: NaiveDate) -> Basket {
+        Basket {
+            date,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// Adds `quantity` portions of `item`, increasing the quantity if the meal is already in
+    /// the basket. Fails with [`MensaError::InvalidQuantity`] if that makes more than
+    /// [`MAX_QUANTITY`] portions of the meal.
+    pub fn add(&mut self, item: &MenuItem, quantity: u32) -> Result<&mut Self> {
+        if quantity == 0 {
+            return Ok(self);
+        }
+        let existing = self.items.iter_mut().find(|i| i.item.md5 == item.md5);
+        let total = existing
+            .as_ref()
+            .map_or(Some(quantity), |i| i.quantity.checked_add(quantity))
+            .filter(|&total| total <= MAX_QUANTITY)
+            .ok_or_else(|| MensaError::InvalidQuantity {
+                md5: item.md5.clone(),
+                quantity,
+            })?;
+        match existing {
+            Some(existing) => existing.quantity = total,
+            None => self.items.push(BasketItem {
+                item: item.clone(),
+                quantity,
+            }),
+        }
+        Ok(self)
+    }
+
+    /// Removes the meal with the given md5 entirely.
+    pub fn remove(&mut self, md5: &str) -> &mut Self {
+        self.items.retain(|i| i.item.md5 != md5);
+        self
+    }
+
+    pub fn items(&self) -> &[BasketItem] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Total to-go price in cents, `None` if the price of any item is unknown.
+    pub fn total(&self) -> Option<u32> {
+        total_price(self.items.iter().map(|i| (i.item.togo_price, i.quantity)))
+    }
+
+    /// md5 and quantity of each position
+    pub(crate) fn positions(&self) -> Vec<(&str, u32)> {
+        self.items
+            .iter()
+            .map(|i| (i.item.md5.as_str(), i.quantity))
+            .collect()
+    }
+}
+
+/// Sum of price times quantity, `None` if any price is unknown or the sum overflows
+pub(crate) fn total_price(positions: impl IntoIterator<Item = (Option<u32>, u32)>) -> Option<u32> {
+    positions
+        .into_iter()
+        .try_fold(0u32, |total, (price, quantity)| {
+            total.checked_add(price?.checked_mul(quantity)?)
+        })
+}
+
+/// Adds the `basket_positions`, `basket_html` and `basket_full` form fields for the given meals
+/// and quantities, as expected by `setDataMensaTogo.php`.
+pub(crate) fn insert_basket_params(
+    params: &mut BTreeMap<String, String>,
+    positions: &[(Meal, u32)],
+) {
+    let mut auflistung_html =
+        "<tbody><tr><th>Anzahl</th> <th>Artikel</th> <th class=\"zahl\">Stückpreis</th></tr> "
+            .to_owned();
+
+    for (meal, quantity) in positions {
+        let a_id = &meal.attributes.artikel_id;
+        let title = &meal.title;
+        let preis_formated_togo = &meal.preis_formated_togo;
+
+        params.insert(format!("basket_positions[{a_id}]"), quantity.to_string());
+
+        auflistung_html += &format!("<tr><td>{quantity}x</td> <td aid_check=\"{a_id}\">{title}</td> <td class=\"preis\">{preis_formated_togo}</td></tr> ");
+
+        let bf = format!("basket_full[{}]", a_id);
+        params.insert(bf.clone() + "[id]", a_id.clone());
+        params.insert(bf.clone() + "[category]", meal.category.clone());
+        params.insert(
+            bf.clone() + "[title]",
+            format!("{} {} {}", title, meal.description, meal.kennz_rest),
+        );
+        params.insert(bf.clone() + "[preis1]", meal.preis1.clone());
+        params.insert(bf.clone() + "[preis2]", meal.preis2.clone());
+        params.insert(bf.clone() + "[preis3]", meal.preis3.clone());
+        params.insert(bf + "[anzahl]", quantity.to_string());
+    }
+
+    auflistung_html += "<tr class=\"trenner\"><td></td> <td></td> <td></td></tr></tbody>";
+
+    params.insert("basket_html".to_owned(), auflistung_html);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, togo_price: Option<u32>) -> MenuItem {
+        let mut item = MenuItem::named(name);
+        item.togo_price = togo_price;
+        item
+    }
+
+    fn basket() -> Basket {
+        Basket::new(NaiveDate::from_ymd_opt(2023, 5, 8).unwrap())
+    }
+
+    #[test]
+    fn quantities_are_added() {
+        let mut basket = basket();
+        let spaetzle = item("Käsespätzle", Some(380));
+        basket
+            .add(&spaetzle, 2)
+            .unwrap()
+            .add(&item("Salat", Some(100)), 1)
+            .unwrap()
+            .add(&spaetzle, 1)
+            .unwrap();
+
+        assert_eq!(basket.positions(), vec![("Käsespätzle", 3), ("Salat", 1)]);
+        assert_eq!(basket.total(), Some(3 * 380 + 100));
+    }
+
+    #[test]
+    fn too_many_portions_are_rejected() {
+        let mut basket = basket();
+        let spaetzle = item("Käsespätzle", Some(380));
+
+        assert!(matches!(
+            basket.add(&spaetzle, u32::MAX),
+            Err(MensaError::InvalidQuantity {
+                quantity: u32::MAX,
+                ..
+            })
+        ));
+        basket.add(&spaetzle, MAX_QUANTITY).unwrap();
+        assert!(basket.add(&spaetzle, 1).is_err());
+        assert!(basket.add(&spaetzle, u32::MAX).is_err());
+        assert_eq!(basket.positions(), vec![("Käsespätzle", MAX_QUANTITY)]);
+    }
+
+    #[test]
+    fn unknown_or_overflowing_total_is_none() {
+        let mut basket = basket();
+        basket
+            .add(&item("Käsespätzle", Some(380)), 1)
+            .unwrap()
+            .add(&item("Tagesangebot", None), 1)
+            .unwrap();
+        assert_eq!(basket.total(), None);
+
+        assert_eq!(total_price([(Some(u32::MAX), 2)]), None);
+        assert_eq!(total_price([(Some(u32::MAX), 1), (Some(1), 1)]), None);
+        assert_eq!(total_price([(Some(380), 2), (Some(100), 1)]), Some(860));
+    }
+}
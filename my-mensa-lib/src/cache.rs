@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Data, Language};
+
+/// Default time a fetched menu is reused.
+pub(crate) const DEFAULT_MENU_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Mensa id and language
+pub(crate) type CacheKey = (i32, Language);
+
+/// Raw menu data. The session it was fetched in is not kept, every order starts its own.
+#[derive(Clone, Debug)]
+struct CachedMenu {
+    data: Data,
+    fetched_at: SystemTime,
+}
+
+impl CachedMenu {
+    fn age(&self) -> Duration {
+        self.fetched_at.elapsed().unwrap_or(Duration::MAX)
+    }
+}
+
+/// On-disk representation of a cache entry.
+#[derive(Serialize, Deserialize)]
+struct PersistedMenu {
+    mensa_id: i32,
+    lang: Language,
+    fetched_at: SystemTime,
+    data: Data,
+}
+
+/// Menus shared by all clones of a [`MensaClient`](crate::MensaClient).
+#[derive(Debug)]
+pub(crate) struct MenuCache {
+    ttl: Duration,
+    file: Option<PathBuf>,
+    entries: Mutex<HashMap<CacheKey, CachedMenu>>,
+    /// Version of the latest change of the entries
+    version: AtomicU64,
+    /// Version last written to `file`, locked while writing
+    written: tokio::sync::Mutex<u64>,
+}
+
+impl MenuCache {
+    /// Creates the cache, loading entries from `file` if it exists.
+    pub fn new(ttl: Duration, file: Option<PathBuf>) -> MenuCache {
+        let entries = file.as_deref().map(load).unwrap_or_default();
+        MenuCache {
+            ttl,
+            file,
+            entries: Mutex::new(entries),
+            version: AtomicU64::new(0),
+            written: tokio::sync::Mutex::new(0),
+        }
+    }
+
+    /// Menu younger than the TTL.
+    pub fn get(&self, key: &CacheKey) -> Option<Data> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|menu| menu.age() < self.ttl)
+            .map(|menu| menu.data.clone())
+    }
+
+    pub async fn insert(&self, key: CacheKey, data: Data) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let snapshot = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert(
+                key,
+                CachedMenu {
+                    data,
+                    fetched_at: SystemTime::now(),
+                },
+            );
+            self.snapshot(&entries)
+        };
+        self.save(snapshot).await;
+    }
+
+    pub async fn clear(&self) {
+        let snapshot = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.clear();
+            self.snapshot(&entries)
+        };
+        self.save(snapshot).await;
+    }
+
+    /// Copy of the entries to be written with their version, `None` without a file. Taken
+    /// while the entries are locked, so the versions follow the order of the changes.
+    fn snapshot(
+        &self,
+        entries: &HashMap<CacheKey, CachedMenu>,
+    ) -> Option<(u64, Vec<PersistedMenu>)> {
+        self.file.as_ref()?;
+        let version = self.version.fetch_add(1, Ordering::Relaxed) + 1;
+        Some((version, persisted(entries)))
+    }
+
+    /// Writes the snapshot to the file, unless a newer one was written meanwhile. Runs outside
+    /// the lock of the entries. Failures are only logged, the in-memory cache stays usable.
+    async fn save(&self, snapshot: Option<(u64, Vec<PersistedMenu>)>) {
+        let (file, (version, menus)) = match (&self.file, snapshot) {
+            (Some(file), Some(snapshot)) => (file, snapshot),
+            _ => return,
+        };
+
+        let mut written = self.written.lock().await;
+        if *written >= version {
+            return;
+        }
+
+        let result = match serde_json::to_vec(&menus) {
+            Ok(json) => tokio::fs::write(file, json).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(()) => *written = version,
+            Err(e) => log::warn!("Failed to write menu cache {}: {}", file.display(), e),
+        }
+    }
+}
+
+fn load(file: &Path) -> HashMap<CacheKey, CachedMenu> {
+    let reader = match File::open(file) {
+        Ok(f) => BufReader::new(f),
+        Err(_) => return HashMap::new(),
+    };
+
+    match serde_json::from_reader::<_, Vec<PersistedMenu>>(reader) {
+        Ok(menus) => menus
+            .into_iter()
+            .map(|m| {
+                let menu = CachedMenu {
+                    data: m.data,
+                    fetched_at: m.fetched_at,
+                };
+                ((m.mensa_id, m.lang), menu)
+            })
+            .collect(),
+        Err(e) => {
+            log::warn!("Ignoring menu cache {}: {}", file.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+fn persisted(entries: &HashMap<CacheKey, CachedMenu>) -> Vec<PersistedMenu> {
+    entries
+        .iter()
+        .map(|((mensa_id, lang), menu)| PersistedMenu {
+            mensa_id: *mensa_id,
+            lang: *lang,
+            fetched_at: menu.fetched_at,
+            data: menu.data.clone(),
+        })
+        .collect()
+}
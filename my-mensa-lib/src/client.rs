@@ -0,0 +1,698 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{NaiveDate, NaiveTime};
+use futures_util::{future::join_all, stream, Stream, StreamExt};
+use linked_hash_map::LinkedHashMap;
+use log::debug;
+use reqwest::{
+    cookie::CookieStore as _,
+    header::{HeaderMap, COOKIE, SET_COOKIE},
+    RequestBuilder, Url,
+};
+use reqwest_cookie_store::CookieStoreMutex;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    basket::{insert_basket_params, total_price},
+    cache::{MenuCache, DEFAULT_MENU_TTL},
+    language::fill_untranslated,
+    mensa::{DEFAULT_MAX_MENSA_ID, DEFAULT_MENSA_LIST_TTL},
+    parse_price,
+    watch::slot_changes,
+    Basket, Data, DayMenu, DietClassifier, Language, Mensa, MensaError, MenuItem,
+    OrderConfirmation, PreparedOrder, Result, SlotChange, TimeSlot, UserProfile,
+};
+
+/// Base URL of the Studierendenwerk Ulm my-mensa instance, hosting `getdata.php` and
+/// `setDataMensaTogo.php`.
+pub const DEFAULT_API_BASE_URL: &str = "https://stwulm.my-mensa.de";
+
+/// Base URL of the to-go API, including the access token path segment.
+pub const DEFAULT_TOGO_API_URL: &str =
+    "https://togo.my-mensa.de/5ecb878c-9f58-4aa0-bb1b/ulm19c552/api";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Canteens found by [`MensaClient::list_mensas`], with the time they were found
+type MensaList = (Instant, Vec<Mensa>);
+
+/// Client for the my-mensa API.
+///
+/// Cloning is cheap, all clones share the same connection pool and menu cache.
+#[derive(Clone, Debug)]
+pub struct MensaClient {
+    http: reqwest::Client,
+    api_base_url: String,
+    togo_api_url: String,
+    diet_classifier: DietClassifier,
+    menu_cache: Arc<MenuCache>,
+    /// Result of the last complete [`MensaClient::list_mensas`] and when it was fetched
+    mensa_list: Arc<tokio::sync::Mutex<Option<MensaList>>>,
+    mensa_list_ttl: Duration,
+    max_mensa_id: i32,
+    retries: u32,
+    retry_backoff: Duration,
+}
+
+/// Builder for [`MensaClient`], obtained via [`MensaClient::builder`].
+#[derive(Clone, Debug)]
+pub struct MensaClientBuilder {
+    api_base_url: String,
+    togo_api_url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    retries: u32,
+    retry_backoff: Duration,
+    user_agent: String,
+    diet_classifier: DietClassifier,
+    menu_cache_ttl: Duration,
+    menu_cache_file: Option<PathBuf>,
+    mensa_list_ttl: Duration,
+    max_mensa_id: i32,
+}
+
+impl Default for MensaClientBuilder {
+    fn default() -> Self {
+        MensaClientBuilder {
+            api_base_url: DEFAULT_API_BASE_URL.to_owned(),
+            togo_api_url: DEFAULT_TOGO_API_URL.to_owned(),
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_owned(),
+            diet_classifier: DietClassifier::default(),
+            menu_cache_ttl: DEFAULT_MENU_TTL,
+            menu_cache_file: None,
+            mensa_list_ttl: DEFAULT_MENSA_LIST_TTL,
+            max_mensa_id: DEFAULT_MAX_MENSA_ID,
+        }
+    }
+}
+
+impl MensaClientBuilder {
+    /// Base URL for `getdata.php` and `setDataMensaTogo.php`.
+    pub fn api_base_url(mut self, url: impl Into<String>) -> Self {
+        self.api_base_url = url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Base URL of the to-go API, `get_free_slots/` is appended to it.
+    pub fn togo_api_url(mut self, url: impl Into<String>) -> Self {
+        self.togo_api_url = url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Total timeout for each request, 10 seconds by default. An order timing out is reported
+    /// as [`MensaError::OrderUnconfirmed`], as it may have been placed anyway.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Timeout for establishing a connection, 5 seconds by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How often menu and slot requests are repeated after a timeout, connection error or
+    /// server outage, 2 by default. Orders are never repeated.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Wait before the first retry, doubled for each further one. 500 ms by default.
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Classifier used to set [`MenuItem::diet`], e.g. with additional overrides.
+    pub fn diet_classifier(mut self, classifier: DietClassifier) -> Self {
+        self.diet_classifier = classifier;
+        self
+    }
+
+    /// How long a fetched menu is reused, 5 minutes by default. [`Duration::ZERO`] disables
+    /// the cache.
+    pub fn menu_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.menu_cache_ttl = ttl;
+        self
+    }
+
+    /// Persists cached menus to this file, and loads them from it when building the client.
+    /// Cached menus are only used for displaying, ordering always fetches the menu again.
+    pub fn menu_cache_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.menu_cache_file = Some(path.into());
+        self
+    }
+
+    /// How long the canteens found by [`MensaClient::list_mensas`] are reused, 24 hours by
+    /// default.
+    pub fn mensa_list_ttl(mut self, ttl: Duration) -> Self {
+        self.mensa_list_ttl = ttl;
+        self
+    }
+
+    /// Highest id probed by [`MensaClient::list_mensas`], 10 by default.
+    pub fn max_mensa_id(mut self, id: i32) -> Self {
+        self.max_mensa_id = id;
+        self
+    }
+
+    pub fn build(self) -> Result<MensaClient> {
+        let http = reqwest::Client::builder()
+            .user_agent(self.user_agent)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+
+        Ok(MensaClient {
+            http,
+            api_base_url: self.api_base_url,
+            togo_api_url: self.togo_api_url,
+            diet_classifier: self.diet_classifier,
+            menu_cache: Arc::new(MenuCache::new(self.menu_cache_ttl, self.menu_cache_file)),
+            mensa_list: Arc::default(),
+            mensa_list_ttl: self.mensa_list_ttl,
+            max_mensa_id: self.max_mensa_id,
+            retries: self.retries,
+            retry_backoff: self.retry_backoff,
+        })
+    }
+}
+
+impl MensaClient {
+    /// Client with default settings, talking to the production servers.
+    pub fn new() -> Result<MensaClient> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> MensaClientBuilder {
+        MensaClientBuilder::default()
+    }
+
+    /// All canteens of the my-mensa instance, found by fetching the menus of ids up to
+    /// [`MensaClientBuilder::max_mensa_id`]. The menus end up in the cache, and the canteens
+    /// are reused for [`MensaClientBuilder::mensa_list_ttl`]. Ids that fail are skipped and
+    /// probed again on the next call, an error is only returned if no canteen could be found
+    /// at all.
+    pub async fn list_mensas(&self) -> Result<Vec<Mensa>> {
+        // Held while probing, so concurrent calls wait for the result instead of probing too
+        let mut cached = self.mensa_list.lock().await;
+        if let Some((fetched, mensas)) = &*cached {
+            if fetched.elapsed() < self.mensa_list_ttl {
+                return Ok(mensas.clone());
+            }
+        }
+
+        let ids = 1..=self.max_mensa_id;
+        let responses = join_all(
+            ids.clone()
+                .map(|id| self.get_menu_data(id, Language::default())),
+        )
+        .await;
+
+        let mut mensas = Vec::new();
+        let mut error = None;
+        for (id, response) in ids.zip(responses) {
+            let data = match response {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Skipping mensa {}: {}", id, e);
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+            // Unknown ids are answered with an empty name and no days
+            if data.mensaname.trim().is_empty() {
+                debug!("No mensa with id {}", id);
+                continue;
+            }
+
+            let togo = data
+                .result
+                .iter()
+                .flat_map(|day| &day.essen)
+                .any(|meal| !meal.preis_formated_togo.trim().is_empty());
+            mensas.push(Mensa::new(id, data.mensaname, togo));
+        }
+
+        match error {
+            Some(e) if mensas.is_empty() => Err(e),
+            Some(_) => Ok(mensas),
+            None => {
+                *cached = Some((Instant::now(), mensas.clone()));
+                Ok(mensas)
+            }
+        }
+    }
+
+    /// Looks up a canteen by id or by a word of its name, e.g. `"west"`.
+    pub async fn find_mensa(&self, query: &str) -> Result<Mensa> {
+        let mut matches: Vec<Mensa> = self
+            .list_mensas()
+            .await?
+            .into_iter()
+            .filter(|m| m.matches(query))
+            .collect();
+
+        match matches.len() {
+            0 => Err(MensaError::MensaNotFound {
+                query: query.to_owned(),
+            }),
+            1 => Ok(matches.remove(0)),
+            _ => Err(MensaError::AmbiguousMensa {
+                query: query.to_owned(),
+                candidates: matches.into_iter().map(|m| m.name).collect(),
+            }),
+        }
+    }
+
+    pub async fn get_free_slots(
+        &self,
+        mensa_id: i32,
+        email: &str,
+        date: NaiveDate,
+    ) -> Result<Vec<TimeSlot>> {
+        let mut params: HashMap<&str, &str> = HashMap::new();
+        let mensa_id_str = mensa_id.to_string();
+        params.insert("mensa_id", &mensa_id_str);
+        let iso_date = date.format("%Y-%m-%d").to_string();
+        params.insert("tag", &iso_date);
+        params.insert("id", email);
+
+        let url = format!("{}/get_free_slots/", self.togo_api_url);
+        log::trace!("Calling API url: {}", &url);
+
+        let (slots, _, _): (LinkedHashMap<String, i32>, _, _) = self
+            .fetch_json("get_free_slots", || self.http.post(&url).form(&params))
+            .await?;
+
+        slots
+            .into_iter()
+            .map(|(label, capacity)| TimeSlot::parse(&label, capacity))
+            .collect()
+    }
+
+    /// Polls the free slots as seen by `email` every `interval` and yields how they changed.
+    /// The first poll reports every free slot as opened. Failed polls are yielded as errors and
+    /// the stream continues, it only ends when dropped.
+    pub fn watch_slots(
+        &self,
+        mensa_id: i32,
+        email: &str,
+        date: NaiveDate,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<SlotChange>> {
+        let client = self.clone();
+        let email = email.to_owned();
+        let polls = stream::unfold(None, move |previous: Option<Vec<TimeSlot>>| {
+            let client = client.clone();
+            let email = email.clone();
+            async move {
+                if previous.is_some() {
+                    tokio::time::sleep(interval).await;
+                }
+                match client.get_free_slots(mensa_id, &email, date).await {
+                    Ok(slots) => {
+                        let changes = slot_changes(previous.as_deref().unwrap_or_default(), &slots);
+                        Some((changes.into_iter().map(Ok).collect(), Some(slots)))
+                    }
+                    Err(e) => {
+                        log::warn!("Polling free slots failed: {}", e);
+                        let next = Some(previous.unwrap_or_default());
+                        Some((vec![Err(e)], next))
+                    }
+                }
+            }
+        });
+        polls.flat_map(stream::iter::<Vec<Result<SlotChange>>>)
+    }
+
+    pub async fn order(
+        &self,
+        date: NaiveDate,
+        md5: &str,
+        mensa_id: i32,
+        user: &UserProfile,
+        time: NaiveTime,
+        lang: Language,
+    ) -> Result<OrderConfirmation> {
+        let order = self
+            .prepare_order(date, md5, mensa_id, user, time, lang)
+            .await?;
+        self.submit_order(order).await
+    }
+
+    /// Orders all meals in the basket for pickup in the slot starting at `time`.
+    pub async fn order_basket(
+        &self,
+        mensa_id: i32,
+        basket: &Basket,
+        user: &UserProfile,
+        time: NaiveTime,
+        lang: Language,
+    ) -> Result<OrderConfirmation> {
+        let order = self
+            .prepare_basket_order(mensa_id, basket, user, time, lang)
+            .await?;
+        self.submit_order(order).await
+    }
+
+    /// Validates the order against the current menu and free slots and builds the form
+    /// parameters, without submitting anything. `md5` has to be taken from the menu in
+    /// language `lang`.
+    pub async fn prepare_order(
+        &self,
+        date: NaiveDate,
+        md5: &str,
+        mensa_id: i32,
+        user: &UserProfile,
+        time: NaiveTime,
+        lang: Language,
+    ) -> Result<PreparedOrder> {
+        self.prepare_positions(mensa_id, date, &[(md5, 1)], user, time, lang)
+            .await
+    }
+
+    /// Like [`MensaClient::prepare_order`], for all meals in the basket.
+    pub async fn prepare_basket_order(
+        &self,
+        mensa_id: i32,
+        basket: &Basket,
+        user: &UserProfile,
+        time: NaiveTime,
+        lang: Language,
+    ) -> Result<PreparedOrder> {
+        self.prepare_positions(
+            mensa_id,
+            basket.date(),
+            &basket.positions(),
+            user,
+            time,
+            lang,
+        )
+        .await
+    }
+
+    async fn prepare_positions(
+        &self,
+        mensa_id: i32,
+        date: NaiveDate,
+        positions: &[(&str, u32)],
+        user: &UserProfile,
+        time: NaiveTime,
+        lang: Language,
+    ) -> Result<PreparedOrder> {
+        if positions.is_empty() {
+            return Err(MensaError::EmptyBasket);
+        }
+
+        // The order has to be sent within the session of the menu. Each order fetches the
+        // menu in its own session, so that orders of different users never share one.
+        let (cookie_store, menu_data) = self.get_menu_impl(mensa_id, lang).await?;
+
+        let day = menu_data
+            .result
+            .into_iter()
+            .find(|day| day.tag.datum_iso == date)
+            .ok_or(MensaError::DayNotFound { date })?;
+
+        let meals = positions
+            .iter()
+            .map(|&(md5, quantity)| {
+                day.essen
+                    .iter()
+                    .find(|m| m.md5 == md5)
+                    .map(|meal| (meal.clone(), quantity))
+                    .ok_or_else(|| MensaError::MealNotFound {
+                        md5: md5.to_owned(),
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let slots = self.get_free_slots(mensa_id, &user.email, date).await?;
+        let slot = slots
+            .into_iter()
+            .find(|slot| slot.start == time)
+            .ok_or(MensaError::SlotNotFound { time })?;
+
+        if !slot.is_free() {
+            return Err(MensaError::SlotFull { slot });
+        }
+
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("client[einrichtung]".to_owned(), menu_data.mensaname);
+
+        let mensa_id_string = mensa_id.to_string();
+        params.insert("client[einrichtung_val]".to_owned(), mensa_id_string);
+
+        params.insert("client[vorname]".to_owned(), user.firstname.clone());
+        params.insert("client[name]".to_owned(), user.lastname.clone());
+        params.insert("client[email]".to_owned(), user.email.clone());
+
+        params.insert("client[nv2]".to_owned(), "true".to_owned());
+        params.insert("client[save_allowed]".to_owned(), "true".to_owned());
+
+        params.insert(
+            "client[deliver_time_val]".to_owned(),
+            slot.start.format("%H:%M").to_string(),
+        );
+        params.insert(
+            "client[date_iso]".to_owned(),
+            date.format("%Y-%m-%d").to_string(),
+        );
+        params.insert("client[date_hr]".to_owned(), day.tag.tag_formatiert2);
+
+        insert_basket_params(&mut params, &meals);
+
+        let total = total_price(
+            meals
+                .iter()
+                .map(|(meal, quantity)| (parse_price(&meal.preis_formated_togo), *quantity)),
+        );
+
+        Ok(PreparedOrder {
+            mensa_id,
+            date,
+            slot,
+            total,
+            language: lang,
+            params,
+            session: cookie_store,
+        })
+    }
+
+    /// Sends a prepared order. This is never retried, as the order might have been placed
+    /// even if the response got lost. A timeout or lost response is reported as
+    /// [`MensaError::OrderUnconfirmed`].
+    pub async fn submit_order(&self, order: PreparedOrder) -> Result<OrderConfirmation> {
+        let url = format!(
+            "{}/setDataMensaTogo.php?order=add&language={}",
+            self.api_base_url,
+            order.language.code()
+        );
+
+        let mut request = self.http.post(url).form(&order.params).build()?;
+        if let Some(cookies) = order.session.cookies(request.url()) {
+            request.headers_mut().insert(COOKIE, cookies);
+        }
+
+        let unconfirmed = |e: reqwest::Error| MensaError::OrderUnconfirmed {
+            reason: e.to_string(),
+            body: String::new(),
+        };
+        let response = match self.http.execute(request).await {
+            Ok(response) => response,
+            // Nothing was sent
+            Err(e) if e.is_connect() => return Err(e.into()),
+            Err(e) => return Err(unconfirmed(e)),
+        };
+        let status = response.status();
+        let body = response.text().await.map_err(unconfirmed)?;
+
+        log::trace!("Order response: {:?}", body);
+
+        // The order might have been stored before the error, e.g. a gateway timeout
+        if matches!(status.as_u16(), 500 | 502 | 504) {
+            return Err(MensaError::OrderUnconfirmed {
+                reason: format!("HTTP {}", status),
+                body,
+            });
+        }
+        if !status.is_success() {
+            return Err(MensaError::OrderRejected {
+                reason: format!("HTTP {}", status),
+                body,
+            });
+        }
+
+        OrderConfirmation::parse(&body, order.date, order.slot, order.total)
+    }
+
+    /// Fetches the raw menu data, together with the session cookies set by the server, and
+    /// stores it in the menu cache. Untranslated meals are filled in from the German menu,
+    /// which is fetched as well if it is not cached.
+    async fn get_menu_impl(
+        &self,
+        mensa_id: i32,
+        lang: Language,
+    ) -> Result<(Arc<CookieStoreMutex>, Data)> {
+        let (cookie_store, mut data) = self.fetch_menu(mensa_id, lang).await?;
+
+        if lang != Language::German {
+            let german = match self.menu_cache.get(&(mensa_id, Language::German)) {
+                Some(german) => german,
+                None => {
+                    let (_, german) = self.fetch_menu(mensa_id, Language::German).await?;
+                    self.menu_cache
+                        .insert((mensa_id, Language::German), german.clone())
+                        .await;
+                    german
+                }
+            };
+            fill_untranslated(&mut data, &german);
+        }
+
+        self.menu_cache.insert((mensa_id, lang), data.clone()).await;
+
+        Ok((cookie_store, data))
+    }
+
+    /// Fetches the raw menu data, together with the session cookies set by the server.
+    async fn fetch_menu(
+        &self,
+        mensa_id: i32,
+        lang: Language,
+    ) -> Result<(Arc<CookieStoreMutex>, Data)> {
+        let (json, headers, url): (Data, _, _) = self
+            .fetch_json("getdata", || {
+                let now = SystemTime::now();
+                let since_the_epoch = now.duration_since(UNIX_EPOCH).unwrap();
+                let now_millis = since_the_epoch.as_millis();
+
+                let url: String = format!(
+                    "{}/getdata.php?mensa_id={mensa_id}&json=1&hyp=1&now={now_millis}&mode=togo&lang={}",
+                    self.api_base_url,
+                    lang.code()
+                );
+                log::trace!("Calling API url: {}", &url);
+
+                self.http.get(url)
+            })
+            .await?;
+
+        let cookie_store = Arc::new(CookieStoreMutex::default());
+        cookie_store.set_cookies(&mut headers.get_all(SET_COOKIE).iter(), &url);
+
+        debug!("Session cookies: {:?}", cookie_store.lock().unwrap());
+
+        Ok((cookie_store, json))
+    }
+
+    /// Sends an idempotent request and decodes the JSON response. Timeouts, connection errors
+    /// and outages are retried with exponential backoff. Also returns the headers and the
+    /// final URL of the response, for the session cookies.
+    async fn fetch_json<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<(T, HeaderMap, Url)> {
+        let mut attempt = 0;
+        loop {
+            match self.fetch_json_once(endpoint, request()).await {
+                Err(e) if e.is_transient() && attempt < self.retries => {
+                    let backoff = self.retry_backoff * 2u32.saturating_pow(attempt);
+                    log::warn!("{} failed, retrying in {:?}: {}", endpoint, backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn fetch_json_once<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        request: RequestBuilder,
+    ) -> Result<(T, HeaderMap, Url)> {
+        let response = request.send().await?;
+        log::trace!("Response: {:?}", &response);
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let url = response.url().clone();
+
+        let response_text = response.text().await?;
+        log::trace!("Text: {:?}", response_text);
+
+        if !status.is_success() {
+            return Err(MensaError::unavailable(
+                endpoint,
+                status.as_u16(),
+                &response_text,
+            ));
+        }
+
+        let json = serde_json::from_str(&response_text)
+            .map_err(|e| MensaError::decode(endpoint, &response_text, e))?;
+
+        Ok((json, headers, url))
+    }
+
+    /// Menu of the next days, from the cache if it was fetched within the TTL.
+    pub async fn get_menu(&self, mensa_id: i32, lang: Language) -> Result<Vec<DayMenu>> {
+        let data = self.get_menu_data(mensa_id, lang).await?;
+        Ok(self.day_menus(data))
+    }
+
+    /// Raw menu data, from the cache if possible.
+    async fn get_menu_data(&self, mensa_id: i32, lang: Language) -> Result<Data> {
+        match self.menu_cache.get(&(mensa_id, lang)) {
+            Some(data) => Ok(data),
+            None => Ok(self.get_menu_impl(mensa_id, lang).await?.1),
+        }
+    }
+
+    /// Like [`MensaClient::get_menu`], but always fetches the menu from the server.
+    pub async fn refresh_menu(&self, mensa_id: i32, lang: Language) -> Result<Vec<DayMenu>> {
+        let (_, data) = self.get_menu_impl(mensa_id, lang).await?;
+        Ok(self.day_menus(data))
+    }
+
+    /// Drops all cached menus, including the persisted ones.
+    pub async fn clear_menu_cache(&self) {
+        self.menu_cache.clear().await;
+    }
+
+    fn day_menus(&self, data: Data) -> Vec<DayMenu> {
+        data.result
+            .into_iter()
+            .map(|day| DayMenu {
+                date: day.tag.datum_iso,
+                meals: day
+                    .essen
+                    .into_iter()
+                    .map(|meal| MenuItem::from_meal(meal, &self.diet_classifier))
+                    .collect(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,232 @@
+use chrono::NaiveDate;
+use serde_json::Value;
+
+use crate::{error::snippet, parse_price, MensaError, Result, TimeSlot};
+
+// No response of an accepted order has been captured yet, so the keys below are assumptions
+// based on the names used by the other my-mensa endpoints. A response matching none of them is
+// reported as unconfirmed, as the order may well have been accepted.
+
+/// Keys of the `setDataMensaTogo.php` response, in order of preference.
+const ORDER_NUMBER_KEYS: &[&str] = &[
+    "bestellnummer",
+    "abholnummer",
+    "order_id",
+    "orderId",
+    "nummer",
+];
+const STATUS_KEYS: &[&str] = &["status", "result", "state"];
+const ERROR_KEYS: &[&str] = &["error", "fehler", "error_msg", "errormsg"];
+const MESSAGE_KEYS: &[&str] = &["message", "msg", "meldung", "text"];
+const TOTAL_KEYS: &[&str] = &["summe", "total", "gesamtpreis"];
+const SLOT_KEYS: &[&str] = &["abholzeit", "deliver_time", "zeit"];
+
+/// Status values meaning the order was not accepted, lowercase.
+const FAILURE_STATUS: &[&str] = &["error", "fail", "failed", "fehler", "false", "nok"];
+/// Status values meaning the order was accepted, lowercase.
+const SUCCESS_STATUS: &[&str] = &["ok", "success", "erfolg", "true", "1"];
+
+/// Accepted order, as reported by the server.
+#[derive(Clone, Debug)]
+pub struct OrderConfirmation {
+    /// Order or pickup number, if the server reported one
+    pub order_number: Option<String>,
+    pub date: NaiveDate,
+    /// Pickup slot, as confirmed by the server if it sent one, otherwise the requested slot
+    pub slot: TimeSlot,
+    /// Total to-go price in cents
+    pub total: Option<u32>,
+    /// Status text sent by the server
+    pub status: Option<String>,
+    /// Unparsed response body
+    pub raw_response: String,
+}
+
+impl OrderConfirmation {
+    /// Parses the response of `setDataMensaTogo.php`. Returns [`MensaError::OrderRejected`] if
+    /// the response indicates a failure, and [`MensaError::OrderUnconfirmed`] if it indicates
+    /// neither failure nor success (an error page, an empty body or an unknown JSON document).
+    ///
+    /// `slot` and `total` are the values of the submitted order, used where the response does
+    /// not contain them.
+    pub(crate) fn parse(
+        body: &str,
+        date: NaiveDate,
+        slot: TimeSlot,
+        total: Option<u32>,
+    ) -> Result<OrderConfirmation> {
+        let rejected = |reason: String| MensaError::OrderRejected {
+            reason,
+            body: body.to_owned(),
+        };
+        let unconfirmed = |reason: String| MensaError::OrderUnconfirmed {
+            reason,
+            body: body.to_owned(),
+        };
+
+        if body.trim().is_empty() {
+            return Err(unconfirmed("Empty response".to_owned()));
+        }
+
+        let json = match serde_json::from_str::<Value>(body) {
+            Ok(Value::Object(json)) => json,
+            _ => {
+                return Err(unconfirmed(format!(
+                    "Unrecognised response: {}",
+                    snippet(body.trim())
+                )))
+            }
+        };
+
+        let find = |keys: &[&str]| -> Option<String> {
+            keys.iter()
+                .filter_map(|k| json.get(*k))
+                .find_map(value_to_string)
+        };
+
+        let message = find(MESSAGE_KEYS);
+        let status = find(STATUS_KEYS);
+
+        if let Some(error) = find(ERROR_KEYS) {
+            if error != "false" && error != "0" {
+                return Err(rejected(message.unwrap_or(error)));
+            }
+        }
+        if let Some(status) = &status {
+            if FAILURE_STATUS.contains(&status.to_lowercase().as_str()) {
+                return Err(rejected(message.unwrap_or_else(|| status.clone())));
+            }
+        }
+        if json.get("success") == Some(&Value::Bool(false)) {
+            return Err(rejected(
+                message.unwrap_or_else(|| "Unsuccessful".to_owned()),
+            ));
+        }
+
+        let order_number = find(ORDER_NUMBER_KEYS);
+        let succeeded = json.get("success") == Some(&Value::Bool(true))
+            || status
+                .as_ref()
+                .is_some_and(|s| SUCCESS_STATUS.contains(&s.to_lowercase().as_str()));
+        if order_number.is_none() && !succeeded {
+            return Err(unconfirmed(format!(
+                "Unrecognised response: {}",
+                snippet(body.trim())
+            )));
+        }
+
+        let slot = find(SLOT_KEYS)
+            .and_then(|s| TimeSlot::parse(&s, slot.capacity).ok())
+            // The server might only send the start time, keep the requested slot if it matches
+            .filter(|confirmed| confirmed.start != slot.start)
+            .unwrap_or(slot);
+
+        Ok(OrderConfirmation {
+            order_number,
+            date,
+            slot,
+            total: find(TOTAL_KEYS).and_then(|t| parse_price(&t)).or(total),
+            status: status.or(message),
+            raw_response: body.to_owned(),
+        })
+    }
+}
+
+/// Non-empty strings, numbers and `false` (to detect `"error": false`)
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    fn parse(body: &str) -> Result<OrderConfirmation> {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let slot = TimeSlot {
+            start: time(11, 30),
+            end: time(11, 45),
+            capacity: 3,
+        };
+        let date = NaiveDate::from_ymd_opt(2023, 5, 8).unwrap();
+        OrderConfirmation::parse(body, date, slot, Some(380))
+    }
+
+    fn rejection(body: &str) -> String {
+        match parse(body) {
+            Err(MensaError::OrderRejected { reason, .. }) => reason,
+            other => panic!("Expected a rejection of {:?}, got {:?}", body, other),
+        }
+    }
+
+    fn unconfirmed(body: &str) -> String {
+        match parse(body) {
+            Err(MensaError::OrderUnconfirmed { reason, .. }) => reason,
+            other => panic!("Expected {:?} to be unconfirmed, got {:?}", body, other),
+        }
+    }
+
+    #[test]
+    fn order_number_is_confirmation() {
+        let confirmation = parse(r#"{"status":"ok","bestellnummer":1234}"#).unwrap();
+        assert_eq!(confirmation.order_number.as_deref(), Some("1234"));
+        assert_eq!(confirmation.status.as_deref(), Some("ok"));
+        assert_eq!(confirmation.total, Some(380));
+        assert_eq!(
+            confirmation.slot.start,
+            NaiveTime::from_hms_opt(11, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn success_without_order_number_is_confirmation() {
+        let confirmation = parse(r#"{"success":true,"summe":"4,10 €"}"#).unwrap();
+        assert_eq!(confirmation.order_number, None);
+        assert_eq!(confirmation.total, Some(410));
+    }
+
+    #[test]
+    fn empty_body_is_unconfirmed() {
+        assert_eq!(unconfirmed(""), "Empty response");
+        assert_eq!(unconfirmed(" \n"), "Empty response");
+    }
+
+    #[test]
+    fn html_is_unconfirmed() {
+        let reason = unconfirmed("<html><body><h1>Bestellung</h1>Vielen Dank!</body></html>");
+        assert!(
+            reason.starts_with("Unrecognised response: <html>"),
+            "{}",
+            reason
+        );
+        assert!(unconfirmed("Warning: mysqli_connect(): Too many connections").contains("mysqli"));
+    }
+
+    #[test]
+    fn error_is_rejected() {
+        assert_eq!(
+            rejection(r#"{"error":"Zeitfenster ist voll"}"#),
+            "Zeitfenster ist voll"
+        );
+        assert_eq!(
+            rejection(r#"{"error":true,"message":"Bitte E-Mail angeben"}"#),
+            "Bitte E-Mail angeben"
+        );
+        assert_eq!(rejection(r#"{"status":"Fehler"}"#), "Fehler");
+        assert_eq!(rejection(r#"{"success":false}"#), "Unsuccessful");
+    }
+
+    #[test]
+    fn unknown_json_is_unconfirmed() {
+        assert!(unconfirmed(r#"{"id":5}"#).starts_with("Unrecognised response"));
+        assert!(unconfirmed(r#"{"error":false}"#).starts_with("Unrecognised response"));
+        assert!(unconfirmed("[]").starts_with("Unrecognised response"));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{labels::codes, Allergen, MenuItem};
+
+/// Dietary classification of a meal, ordered from most to least restrictive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Diet {
+    Vegan,
+    Vegetarian,
+    Fish,
+    Meat,
+}
+
+/// Keywords in meal titles hinting at meat, lowercase. A keyword matches a word starting or
+/// ending with it, so compounds like "Schweinebraten" or "Putenschnitzel" are found, but not
+/// "Flammkuchen".
+const MEAT_KEYWORDS: &[&str] = &[
+    "schwein",
+    "rind",
+    "kalb",
+    "lamm",
+    "hähnchen",
+    "huhn",
+    "hühner",
+    "pute",
+    "geflügel",
+    "entenbrust",
+    "hirsch",
+    "schnitzel",
+    "wurst",
+    "würste",
+    "würstchen",
+    "speck",
+    "schinken",
+    "hack",
+    "gulasch",
+    "döner",
+    "gyros",
+    "leberkäse",
+    "frikadelle",
+    "bolognese",
+    "salami",
+    "chicken",
+    "beef",
+    "pork",
+];
+
+/// Keywords in meal titles hinting at fish or seafood, lowercase.
+const FISH_KEYWORDS: &[&str] = &[
+    "fisch",
+    "lachs",
+    "seelachs",
+    "kabeljau",
+    "thunfisch",
+    "forelle",
+    "hering",
+    "scholle",
+    "garnele",
+    "shrimp",
+    "krabbe",
+    "tintenfisch",
+    "calamari",
+    "fish",
+    "salmon",
+    "tuna",
+];
+
+/// Word prefixes turning a following meat or fish keyword into a substitute, e.g. "Sojahack",
+/// "Gemüseschnitzel" or "vegane Bolognese", lowercase.
+const SUBSTITUTE_PREFIXES: &[&str] = &[
+    "vegan", "vegetar", "veggie", "soja", "tofu", "seitan", "tempeh", "lupine", "gemüse",
+    "sellerie", "pilz", "linsen", "plant",
+];
+
+/// Words separating the parts of a meal title, a substitute prefix only applies within its
+/// part: "Gemüsepfanne mit Hähnchen" contains meat.
+const PART_SEPARATORS: &[&str] = &["mit", "und", "dazu", "with", "and"];
+
+impl Diet {
+    pub const ALL: [Diet; 4] = [Diet::Vegan, Diet::Vegetarian, Diet::Fish, Diet::Meat];
+
+    pub fn name_de(self) -> &'static str {
+        match self {
+            Diet::Vegan => "vegan",
+            Diet::Vegetarian => "vegetarisch",
+            Diet::Fish => "Fisch",
+            Diet::Meat => "Fleisch",
+        }
+    }
+
+    pub fn name_en(self) -> &'static str {
+        match self {
+            Diet::Vegan => "vegan",
+            Diet::Vegetarian => "vegetarian",
+            Diet::Fish => "fish",
+            Diet::Meat => "meat",
+        }
+    }
+
+    /// Whether a meal of this classification can be eaten by someone following `diet`,
+    /// e.g. a vegan meal is suitable for vegetarians, and a fish meal for pescetarians.
+    pub fn is_suitable_for(self, diet: Diet) -> bool {
+        self <= diet
+    }
+
+    /// Meal type codes that appear in `kennzRest` next to the allergens.
+    pub(crate) fn from_code(code: &str) -> Option<Diet> {
+        match code {
+            "VG" | "vegan" => Some(Diet::Vegan),
+            "V" | "veg" => Some(Diet::Vegetarian),
+            "F" | "MSC" => Some(Diet::Fish),
+            "S" | "R" | "G" | "L" | "W" => Some(Diet::Meat),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Diet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name_en())
+    }
+}
+
+/// Parses the English or German name, `"pescetarian"` and `"pescetarisch"` are accepted for
+/// [`Diet::Fish`].
+impl FromStr for Diet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("pescetarian") || s.eq_ignore_ascii_case("pescetarisch") {
+            return Ok(Diet::Fish);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|d| d.name_en().eq_ignore_ascii_case(s) || d.name_de().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown diet: {}", s))
+    }
+}
+
+/// Derives the [`Diet`] of menu items from their labelling codes, category and title.
+///
+/// Overrides take precedence over all other rules and are matched case-insensitively against
+/// the meal name, so misclassified meals can be fixed without a library update. Next come the
+/// meal type codes and a vegan or vegetarian category. Only meals without either are
+/// classified by keywords in their title.
+#[derive(Clone, Debug, Default)]
+pub struct DietClassifier {
+    overrides: Vec<(String, Diet)>,
+}
+
+impl DietClassifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify all meals whose name contains `keyword` as `diet`.
+    pub fn with_override(mut self, keyword: impl Into<String>, diet: Diet) -> Self {
+        self.overrides.push((keyword.into().to_lowercase(), diet));
+        self
+    }
+
+    /// Returns `None` if there is no indication at all.
+    pub fn classify(&self, item: &MenuItem) -> Option<Diet> {
+        let name = item.name.to_lowercase();
+
+        if let Some((_, diet)) = self.overrides.iter().find(|(k, _)| name.contains(k)) {
+            return Some(*diet);
+        }
+
+        let category = item.category.to_lowercase();
+
+        // Within each group of evidence the least restrictive wins
+        let mut labelled: Vec<Diet> = codes(&item.labels).filter_map(Diet::from_code).collect();
+        if category.contains("vegan") {
+            labelled.push(Diet::Vegan);
+        } else if category.contains("vegetar") || category.contains("veggie") {
+            labelled.push(Diet::Vegetarian);
+        }
+
+        let mut diet = match labelled.into_iter().max() {
+            Some(diet) => Some(diet),
+            None => title_diet(&name, category.contains("fisch")),
+        };
+
+        // Declared allergens are reliable as well
+        if [Allergen::Fish, Allergen::Crustaceans, Allergen::Molluscs]
+            .iter()
+            .any(|a| item.allergens.contains(a))
+        {
+            diet = diet.max(Some(Diet::Fish));
+        }
+
+        let diet = diet?;
+
+        if diet == Diet::Vegan
+            && (item.allergens.contains(&Allergen::Milk)
+                || item.allergens.contains(&Allergen::Eggs))
+        {
+            return Some(Diet::Vegetarian);
+        }
+
+        Some(diet)
+    }
+}
+
+/// Classification by keywords in the lowercase title, `fish_category` for a fish category
+fn title_diet(name: &str, fish_category: bool) -> Option<Diet> {
+    let mut evidence = vec![];
+    if fish_category {
+        evidence.push(Diet::Fish);
+    }
+
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    for part in words.split(|w| PART_SEPARATORS.contains(w)) {
+        let mut substitute = false;
+        for word in part {
+            let is_substitute = SUBSTITUTE_PREFIXES.iter().any(|p| word.starts_with(p));
+            if word.starts_with("vegan") {
+                evidence.push(Diet::Vegan);
+            }
+            substitute |= is_substitute;
+            if substitute {
+                continue;
+            }
+
+            if matches_keyword(word, FISH_KEYWORDS) {
+                evidence.push(Diet::Fish);
+            }
+            if matches_keyword(word, MEAT_KEYWORDS) {
+                evidence.push(Diet::Meat);
+            }
+        }
+    }
+
+    evidence.into_iter().max()
+}
+
+fn matches_keyword(word: &str, keywords: &[&str]) -> bool {
+    keywords
+        .iter()
+        .any(|k| word.starts_with(k) || word.ends_with(k))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, labels: &str) -> MenuItem {
+        let mut item = MenuItem::named(name);
+        item.labels = labels.to_owned();
+        item
+    }
+
+    fn classify(name: &str, labels: &str) -> Option<Diet> {
+        DietClassifier::new().classify(&item(name, labels))
+    }
+
+    #[test]
+    fn meat_in_compounds_is_found() {
+        assert_eq!(classify("Schweinebraten mit Knödel", ""), Some(Diet::Meat));
+        assert_eq!(classify("Putenschnitzel", ""), Some(Diet::Meat));
+        assert_eq!(classify("Nürnberger Bratwürste", ""), Some(Diet::Meat));
+        assert_eq!(classify("Hackbraten", ""), Some(Diet::Meat));
+        assert_eq!(classify("Spaghetti Bolognese", ""), Some(Diet::Meat));
+        assert_eq!(classify("Seelachsfilet", ""), Some(Diet::Fish));
+    }
+
+    #[test]
+    fn keywords_inside_words_are_ignored() {
+        assert_eq!(classify("Flammkuchen", ""), None);
+        assert_eq!(classify("Gemüsepfanne mit gehackten Kräutern", ""), None);
+    }
+
+    #[test]
+    fn substitutes_are_not_meat() {
+        assert_eq!(classify("Sojahack mit Reis", ""), None);
+        assert_eq!(classify("Gemüseschnitzel", ""), None);
+        assert_eq!(classify("Veganes Wiener Schnitzel", ""), Some(Diet::Vegan));
+        assert_eq!(classify("Vegane Bolognese", ""), Some(Diet::Vegan));
+    }
+
+    #[test]
+    fn substitute_only_applies_to_its_part() {
+        assert_eq!(classify("Gemüsepfanne mit Hähnchen", ""), Some(Diet::Meat));
+    }
+
+    #[test]
+    fn labels_take_precedence_over_title() {
+        assert_eq!(classify("Veganes Schnitzel", "VG"), Some(Diet::Vegan));
+        assert_eq!(classify("Gemüseschnitzel", "V,Gl"), Some(Diet::Vegetarian));
+        assert_eq!(
+            classify("Schnitzel Wiener Art", "V"),
+            Some(Diet::Vegetarian)
+        );
+        assert_eq!(classify("Gemüsepfanne", "S"), Some(Diet::Meat));
+    }
+
+    #[test]
+    fn overrides_win() {
+        let classifier = DietClassifier::new().with_override("flammkuchen", Diet::Meat);
+        let mut item = item("Flammkuchen Elsässer Art", "VG");
+        assert_eq!(classifier.classify(&item), Some(Diet::Meat));
+        item.name = "Kartoffelsuppe".to_owned();
+        assert_eq!(classifier.classify(&item), Some(Diet::Vegan));
+    }
+}
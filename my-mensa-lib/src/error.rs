@@ -0,0 +1,116 @@
+use chrono::{NaiveDate, NaiveTime};
+use thiserror::Error;
+
+use crate::{basket::MAX_QUANTITY, TimeSlot};
+
+/// Maximum number of characters of a response body kept in [`MensaError::Decode`].
+const BODY_SNIPPET_LEN: usize = 200;
+
+pub type Result<T, E = MensaError> = std::result::Result<T, E>;
+
+/// Errors returned by the my-mensa API functions.
+#[derive(Debug, Error)]
+pub enum MensaError {
+    #[error("Mensa not found: {query}")]
+    MensaNotFound { query: String },
+
+    #[error("Mensa name is ambiguous: {query} could be {}", candidates.join(", "))]
+    AmbiguousMensa {
+        query: String,
+        candidates: Vec<String>,
+    },
+
+    #[error("Day not found in menu: {date}")]
+    DayNotFound { date: NaiveDate },
+
+    #[error("Meal with md5 not found in menu: {md5}")]
+    MealNotFound { md5: String },
+
+    #[error("Cannot order an empty basket")]
+    EmptyBasket,
+
+    #[error(
+        "Invalid quantity of meal {md5}: {quantity}, at most {} portions can be ordered",
+        MAX_QUANTITY
+    )]
+    InvalidQuantity { md5: String, quantity: u32 },
+
+    #[error("Time slot not found: {}", time.format("%H:%M"))]
+    SlotNotFound { time: NaiveTime },
+
+    #[error("Time slot full: {slot}")]
+    SlotFull { slot: TimeSlot },
+
+    #[error("Invalid time slot: {slot}")]
+    InvalidTimeSlot { slot: String },
+
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server answered with an error status or an error page instead of JSON, usually
+    /// because it is overloaded. `status` is 200 for an error page.
+    #[error("Mensa server unavailable ({endpoint}, HTTP {status})")]
+    ServerUnavailable {
+        endpoint: &'static str,
+        status: u16,
+        body_snippet: String,
+    },
+
+    /// The server answered with JSON that is not the expected document.
+    #[error("Failed to decode {endpoint} response: {source}")]
+    Decode {
+        endpoint: &'static str,
+        body_snippet: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// The order endpoint did not accept the order.
+    #[error("Order rejected by server: {reason}")]
+    OrderRejected { reason: String, body: String },
+
+    /// The order was sent, but the response does not say whether it was accepted. It may have
+    /// been placed, so it must not be repeated before checking the confirmation email.
+    #[error("Order not confirmed by server, it may have been placed anyway: {reason}")]
+    OrderUnconfirmed { reason: String, body: String },
+}
+
+impl MensaError {
+    /// Decoding error for `body`, [`MensaError::ServerUnavailable`] if it is not JSON at all,
+    /// e.g. an HTML error page or a PHP warning.
+    pub(crate) fn decode(endpoint: &'static str, body: &str, source: serde_json::Error) -> Self {
+        if !body.trim_start().starts_with(['{', '[']) {
+            return MensaError::unavailable(endpoint, 200, body);
+        }
+        MensaError::Decode {
+            endpoint,
+            body_snippet: snippet(body),
+            source,
+        }
+    }
+
+    pub(crate) fn unavailable(endpoint: &'static str, status: u16, body: &str) -> Self {
+        MensaError::ServerUnavailable {
+            endpoint,
+            status,
+            body_snippet: snippet(body),
+        }
+    }
+
+    /// Whether the request may succeed when repeated later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            MensaError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            // 200 means an error page instead of JSON
+            MensaError::ServerUnavailable { status, .. } => {
+                *status == 200 || *status == 429 || *status >= 500
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The first [`BODY_SNIPPET_LEN`] characters of `body`
+pub(crate) fn snippet(body: &str) -> String {
+    body.chars().take(BODY_SNIPPET_LEN).collect()
+}
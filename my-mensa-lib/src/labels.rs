@@ -0,0 +1,323 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Diet;
+
+/// Allergens as labelled on the menu (`kennzRest`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Allergen {
+    Gluten,
+    Wheat,
+    Rye,
+    Barley,
+    Oats,
+    Spelt,
+    Crustaceans,
+    Eggs,
+    Fish,
+    Peanuts,
+    Soy,
+    Milk,
+    Nuts,
+    Almonds,
+    Hazelnuts,
+    Walnuts,
+    Cashews,
+    Pecans,
+    BrazilNuts,
+    Pistachios,
+    Macadamias,
+    Celery,
+    Mustard,
+    Sesame,
+    Sulphites,
+    Lupin,
+    Molluscs,
+}
+
+impl Allergen {
+    pub const ALL: [Allergen; 27] = [
+        Allergen::Gluten,
+        Allergen::Wheat,
+        Allergen::Rye,
+        Allergen::Barley,
+        Allergen::Oats,
+        Allergen::Spelt,
+        Allergen::Crustaceans,
+        Allergen::Eggs,
+        Allergen::Fish,
+        Allergen::Peanuts,
+        Allergen::Soy,
+        Allergen::Milk,
+        Allergen::Nuts,
+        Allergen::Almonds,
+        Allergen::Hazelnuts,
+        Allergen::Walnuts,
+        Allergen::Cashews,
+        Allergen::Pecans,
+        Allergen::BrazilNuts,
+        Allergen::Pistachios,
+        Allergen::Macadamias,
+        Allergen::Celery,
+        Allergen::Mustard,
+        Allergen::Sesame,
+        Allergen::Sulphites,
+        Allergen::Lupin,
+        Allergen::Molluscs,
+    ];
+
+    /// Code, German name and English name
+    fn info(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Allergen::Gluten => ("Gl", "Glutenhaltiges Getreide", "Cereals containing gluten"),
+            Allergen::Wheat => ("Wz", "Weizen", "Wheat"),
+            Allergen::Rye => ("Ro", "Roggen", "Rye"),
+            Allergen::Barley => ("Ge", "Gerste", "Barley"),
+            Allergen::Oats => ("Hf", "Hafer", "Oats"),
+            Allergen::Spelt => ("Di", "Dinkel", "Spelt"),
+            Allergen::Crustaceans => ("Kr", "Krebstiere", "Crustaceans"),
+            Allergen::Eggs => ("Ei", "Eier", "Eggs"),
+            Allergen::Fish => ("Fi", "Fisch", "Fish"),
+            Allergen::Peanuts => ("Er", "Erdnüsse", "Peanuts"),
+            Allergen::Soy => ("So", "Soja", "Soy"),
+            Allergen::Milk => ("Mi", "Milch und Laktose", "Milk and lactose"),
+            Allergen::Nuts => ("Nu", "Schalenfrüchte", "Tree nuts"),
+            Allergen::Almonds => ("Ma", "Mandeln", "Almonds"),
+            Allergen::Hazelnuts => ("Ha", "Haselnüsse", "Hazelnuts"),
+            Allergen::Walnuts => ("Wa", "Walnüsse", "Walnuts"),
+            Allergen::Cashews => ("Ca", "Cashewnüsse", "Cashews"),
+            Allergen::Pecans => ("Pe", "Pekannüsse", "Pecans"),
+            Allergen::BrazilNuts => ("Pa", "Paranüsse", "Brazil nuts"),
+            Allergen::Pistachios => ("Pi", "Pistazien", "Pistachios"),
+            Allergen::Macadamias => ("Mc", "Macadamianüsse", "Macadamia nuts"),
+            Allergen::Celery => ("Sl", "Sellerie", "Celery"),
+            Allergen::Mustard => ("Sf", "Senf", "Mustard"),
+            Allergen::Sesame => ("Se", "Sesam", "Sesame"),
+            Allergen::Sulphites => ("Sw", "Schwefeldioxid und Sulfite", "Sulphites"),
+            Allergen::Lupin => ("Lu", "Lupine", "Lupin"),
+            Allergen::Molluscs => ("Wt", "Weichtiere", "Molluscs"),
+        }
+    }
+
+    /// Labelling code as used on the menu, e.g. `"Gl"`
+    pub fn code(self) -> &'static str {
+        self.info().0
+    }
+
+    pub fn name_de(self) -> &'static str {
+        self.info().1
+    }
+
+    pub fn name_en(self) -> &'static str {
+        self.info().2
+    }
+
+    pub fn from_code(code: &str) -> Option<Allergen> {
+        Self::ALL
+            .into_iter()
+            .find(|a| a.code().eq_ignore_ascii_case(code))
+    }
+}
+
+impl fmt::Display for Allergen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name_en())
+    }
+}
+
+/// Parses either a labelling code or the English or German name.
+impl FromStr for Allergen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_code(s)
+            .or_else(|| {
+                Self::ALL.into_iter().find(|a| {
+                    a.name_en().eq_ignore_ascii_case(s) || a.name_de().eq_ignore_ascii_case(s)
+                })
+            })
+            .ok_or_else(|| format!("Unknown allergen: {}", s))
+    }
+}
+
+/// Additives as labelled on the menu (`kennzRest`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Additive {
+    Colouring,
+    Preservative,
+    Antioxidant,
+    FlavourEnhancer,
+    Sulphurised,
+    Blackened,
+    Waxed,
+    Phosphate,
+    Sweetener,
+    Phenylalanine,
+    Caffeine,
+    Quinine,
+}
+
+impl Additive {
+    pub const ALL: [Additive; 12] = [
+        Additive::Colouring,
+        Additive::Preservative,
+        Additive::Antioxidant,
+        Additive::FlavourEnhancer,
+        Additive::Sulphurised,
+        Additive::Blackened,
+        Additive::Waxed,
+        Additive::Phosphate,
+        Additive::Sweetener,
+        Additive::Phenylalanine,
+        Additive::Caffeine,
+        Additive::Quinine,
+    ];
+
+    /// Code, German name and English name
+    fn info(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Additive::Colouring => ("1", "mit Farbstoff", "with colouring"),
+            Additive::Preservative => ("2", "mit Konservierungsstoff", "with preservative"),
+            Additive::Antioxidant => ("3", "mit Antioxidationsmittel", "with antioxidant"),
+            Additive::FlavourEnhancer => ("4", "mit Geschmacksverstärker", "with flavour enhancer"),
+            Additive::Sulphurised => ("5", "geschwefelt", "sulphurised"),
+            Additive::Blackened => ("6", "geschwärzt", "blackened"),
+            Additive::Waxed => ("7", "gewachst", "waxed"),
+            Additive::Phosphate => ("8", "mit Phosphat", "with phosphate"),
+            Additive::Sweetener => ("9", "mit Süßungsmittel", "with sweetener"),
+            Additive::Phenylalanine => (
+                "10",
+                "enthält eine Phenylalaninquelle",
+                "contains a source of phenylalanine",
+            ),
+            Additive::Caffeine => ("11", "koffeinhaltig", "contains caffeine"),
+            Additive::Quinine => ("12", "chininhaltig", "contains quinine"),
+        }
+    }
+
+    /// Labelling code as used on the menu, e.g. `"2"`
+    pub fn code(self) -> &'static str {
+        self.info().0
+    }
+
+    pub fn name_de(self) -> &'static str {
+        self.info().1
+    }
+
+    pub fn name_en(self) -> &'static str {
+        self.info().2
+    }
+
+    pub fn from_code(code: &str) -> Option<Additive> {
+        Self::ALL.into_iter().find(|a| a.code() == code)
+    }
+}
+
+impl fmt::Display for Additive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name_en())
+    }
+}
+
+/// Allergens and additives of a meal, parsed from its labelling codes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labels {
+    pub allergens: BTreeSet<Allergen>,
+    pub additives: BTreeSet<Additive>,
+}
+
+impl Labels {
+    /// Parses a `kennzRest` string such as `"(2,3,Gl,Wz,Ei)"`. Unknown codes are ignored and
+    /// logged once per code, as cached menus are parsed again on every access.
+    pub fn parse(codes: &str) -> Labels {
+        let mut labels = Labels::default();
+
+        for code in self::codes(codes) {
+            if let Some(allergen) = Allergen::from_code(code) {
+                labels.allergens.insert(allergen);
+            } else if let Some(additive) = Additive::from_code(code) {
+                labels.additives.insert(additive);
+            } else if Diet::from_code(code).is_some() {
+                // Meal type markers are evaluated by the `DietClassifier`
+            } else {
+                warn_unknown(code, codes);
+            }
+        }
+
+        labels
+    }
+}
+
+fn warn_unknown(code: &str, codes: &str) {
+    static WARNED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    let mut warned = WARNED.get_or_init(Default::default).lock().unwrap();
+    if warned.insert(code.to_owned()) {
+        log::warn!("Unknown labelling code {:?} in {:?}", code, codes);
+    } else {
+        log::debug!("Unknown labelling code {:?} in {:?}", code, codes);
+    }
+}
+
+/// Splits a `kennzRest` string into the individual codes.
+pub(crate) fn codes(codes: &str) -> impl Iterator<Item = &str> {
+    codes
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|c| !c.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allergens_and_additives_are_parsed() {
+        let labels = Labels::parse("(2,3,Gl,Wz,Ei)");
+
+        assert_eq!(
+            labels.allergens,
+            BTreeSet::from([Allergen::Gluten, Allergen::Wheat, Allergen::Eggs])
+        );
+        assert_eq!(
+            labels.additives,
+            BTreeSet::from([Additive::Preservative, Additive::Antioxidant])
+        );
+    }
+
+    #[test]
+    fn meal_types_and_unknown_codes_are_skipped() {
+        let labels = Labels::parse("(VG,So,Xy,Sl,Xy)");
+
+        assert_eq!(
+            labels.allergens,
+            BTreeSet::from([Allergen::Soy, Allergen::Celery])
+        );
+        assert!(labels.additives.is_empty());
+    }
+
+    #[test]
+    fn separators_and_empty_codes_are_ignored() {
+        assert_eq!(Labels::parse(""), Labels::default());
+        assert_eq!(Labels::parse("()"), Labels::default());
+        assert_eq!(
+            Labels::parse(" Mi ; 11,,Fi "),
+            Labels {
+                allergens: BTreeSet::from([Allergen::Milk, Allergen::Fish]),
+                additives: BTreeSet::from([Additive::Caffeine]),
+            }
+        );
+    }
+
+    #[test]
+    fn allergen_codes_ignore_case() {
+        assert_eq!(
+            Labels::parse("(GL,mi)").allergens,
+            BTreeSet::from([Allergen::Gluten, Allergen::Milk])
+        );
+    }
+}
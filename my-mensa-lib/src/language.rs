@@ -0,0 +1,101 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Data, Meal};
+
+/// Language of the menu texts. Meals without a translation keep their German texts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "de")]
+    German,
+    #[serde(rename = "en")]
+    English,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::German, Language::English];
+
+    /// Code used by the my-mensa API, e.g. `"de"`
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::German => "de",
+            Language::English => "en",
+        }
+    }
+
+    /// Name of the language in itself, e.g. `"Deutsch"`
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Language::German => "Deutsch",
+            Language::English => "English",
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.native_name())
+    }
+}
+
+/// Parses the code, the English or the native name.
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "german" => return Ok(Language::German),
+            "englisch" => return Ok(Language::English),
+            _ => {}
+        }
+        Self::ALL
+            .into_iter()
+            .find(|l| l.code().eq_ignore_ascii_case(s) || l.native_name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown language: {}", s))
+    }
+}
+
+/// Fills in what the menu lacks a translation for from the German menu. How the server sends
+/// untranslated meals is not known, so both an empty title and a missing meal are handled:
+/// meals without a title get the German texts, and meals or days of the German menu that are
+/// missing are added in German. Meals are matched by md5 or article id.
+pub(crate) fn fill_untranslated(data: &mut Data, german: &Data) {
+    for german_day in &german.result {
+        let day = match data
+            .result
+            .iter_mut()
+            .find(|day| day.tag.datum_iso == german_day.tag.datum_iso)
+        {
+            Some(day) => day,
+            None => {
+                data.result.push(german_day.clone());
+                continue;
+            }
+        };
+
+        for german_meal in &german_day.essen {
+            match day
+                .essen
+                .iter_mut()
+                .find(|meal| same_meal(meal, german_meal))
+            {
+                Some(meal) if meal.title_clean.trim().is_empty() => {
+                    meal.title_clean = german_meal.title_clean.clone();
+                    meal.description_clean = german_meal.description_clean.clone();
+                    meal.title = german_meal.title.clone();
+                    meal.description = german_meal.description.clone();
+                }
+                Some(_) => {}
+                None => day.essen.push(german_meal.clone()),
+            }
+        }
+    }
+    data.result.sort_by_key(|day| day.tag.datum_iso);
+}
+
+fn same_meal(meal: &Meal, other: &Meal) -> bool {
+    meal.md5 == other.md5 || meal.attributes.artikel_id == other.attributes.artikel_id
+}
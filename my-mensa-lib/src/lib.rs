@@ -1,31 +1,49 @@
-use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
-use anyhow::{anyhow, Context, Result};
+mod basket;
+mod cache;
+mod client;
+mod confirmation;
+mod diet;
+mod error;
+mod labels;
+mod language;
+mod mensa;
+mod prepared;
+mod price;
+mod slot;
+mod watch;
+
+use std::collections::BTreeSet;
+
+use chrono::{NaiveDate, NaiveTime};
 pub use linked_hash_map::LinkedHashMap;
-use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
-use serde::Deserialize;
-
-use log::debug;
-
-const API_BASE_URL: &str = "https://stwulm.my-mensa.de";
-
-#[derive(Deserialize, Debug)]
+use serde::{Deserialize, Serialize};
+
+pub use basket::{Basket, BasketItem, MAX_QUANTITY};
+pub use client::{MensaClient, MensaClientBuilder, DEFAULT_API_BASE_URL, DEFAULT_TOGO_API_URL};
+pub use confirmation::OrderConfirmation;
+pub use diet::{Diet, DietClassifier};
+pub use error::{MensaError, Result};
+pub use labels::{Additive, Allergen, Labels};
+pub use language::Language;
+pub use mensa::{Mensa, DEFAULT_MENSA_ID};
+pub use prepared::PreparedOrder;
+pub use price::{format_price, parse_price, PriceGroup, Prices};
+pub use slot::TimeSlot;
+pub use watch::SlotChange;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct DayInfo {
-    datum_iso: String,
+    datum_iso: NaiveDate,
     tag_formatiert2: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct MealAttributes {
     #[serde(rename = "artikelId")]
     artikel_id: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Meal {
     title_clean: String,
     description_clean: String,
@@ -43,13 +61,13 @@ struct Meal {
     preis_formated_togo: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Day {
     tag: DayInfo,
     essen: Vec<Meal>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Data {
     mensaname: String,
     result: Vec<Day>,
@@ -72,191 +90,168 @@ impl UserProfile {
     }
 }
 
-pub async fn get_free_slots(
-    mensa_id: i32,
-    email: &str,
-    iso_date: &str,
-) -> Result<LinkedHashMap<String, i32>> {
-    let client = reqwest::Client::builder()
-        .build()
-        .context("Creating HTTP client failed")?;
-
-    let mut params: HashMap<&str, &str> = HashMap::new();
-    let mensa_id_str = mensa_id.to_string();
-    params.insert("mensa_id", &mensa_id_str);
-    params.insert("tag", iso_date);
-    params.insert("id", email);
-
-    let url = "https://togo.my-mensa.de/5ecb878c-9f58-4aa0-bb1b/ulm19c552/api/get_free_slots/";
-    log::trace!("Calling API url: {}", &url);
-
-    let result = client.post(url).form(&params).send().await.context("")?;
-    log::trace!("Response: {:?}", &result);
-
-    let response_text = result.text().await?;
-    log::trace!("Text: {:?}", response_text);
-
-    let json =
-        serde_json::from_str(&response_text).context("Failed to decode get_free_slots response")?;
+/// All canteens of the my-mensa instance, see [`MensaClient::list_mensas`].
+pub async fn list_mensas() -> Result<Vec<Mensa>> {
+    MensaClient::new()?.list_mensas().await
+}
 
-    Ok(json)
+pub async fn get_free_slots(mensa_id: i32, email: &str, date: NaiveDate) -> Result<Vec<TimeSlot>> {
+    MensaClient::new()?
+        .get_free_slots(mensa_id, email, date)
+        .await
 }
 
 pub async fn order(
-    iso_date: &str,
+    date: NaiveDate,
     md5: &str,
     mensa_id: i32,
     user: &UserProfile,
-    time: &str,
-) -> Result<String> {
-    let (cookie_store, menu_data) = get_menu_impl(mensa_id).await?;
-
-    let day = menu_data
-        .result
-        .into_iter()
-        .find(|day| day.tag.datum_iso == iso_date)
-        .unwrap_or_else(|| panic!("Day not found in menu: {}", iso_date));
-
-    let meal = day
-        .essen
-        .into_iter()
-        .find(|m| m.md5 == md5)
-        .unwrap_or_else(|| panic!("Meal with md5 not found in menu: {}", md5));
-
-    let slots = get_free_slots(mensa_id, &user.email, iso_date).await?;
-    let (slot_time, slot_free) = slots
-        .into_iter()
-        .find(|(k, _v)| k.starts_with(time))
-        .ok_or(anyhow!("Time slot not found"))?;
-
-    if slot_free <= 0 {
-        return Err(anyhow!("Time slot full!"));
-    }
-
-    let slot_time = &slot_time[..5];
-
-    let a_id = meal.attributes.artikel_id;
-
-    let client = reqwest::Client::builder()
-        .cookie_provider(cookie_store)
-        .build()
-        .context("Creating HTTP client failed")?;
-
-    let mut params: HashMap<String, String> = HashMap::new();
-    params.insert("client[einrichtung]".to_owned(), menu_data.mensaname);
-
-    let mensa_id_string = mensa_id.to_string();
-    params.insert("client[einrichtung_val]".to_owned(), mensa_id_string);
-
-    params.insert("client[vorname]".to_owned(), user.firstname.clone());
-    params.insert("client[name]".to_owned(), user.lastname.clone());
-    params.insert("client[email]".to_owned(), user.email.clone());
-
-    params.insert("client[nv2]".to_owned(), "true".to_owned());
-    params.insert("client[save_allowed]".to_owned(), "true".to_owned());
-
-    params.insert("client[deliver_time_val]".to_owned(), slot_time.to_owned());
-    params.insert("client[date_iso]".to_owned(), iso_date.to_owned());
-    params.insert("client[date_hr]".to_owned(), day.tag.tag_formatiert2);
-
-    params.insert(format!("basket_positions[{a_id}]"), "1".to_owned());
-
-    let title = meal.title;
-    let preis_formated_togo = meal.preis_formated_togo;
-
-    let auflistung_html = format!("<tbody><tr><th>Anzahl</th> <th>Artikel</th> <th class=\"zahl\">Stückpreis</th></tr> <tr><td>1x</td> <td aid_check=\"{a_id}\">{title}</td> <td class=\"preis\">{preis_formated_togo}</td></tr> <tr class=\"trenner\"><td></td> <td></td> <td></td></tr></tbody>");
+    time: NaiveTime,
+    lang: Language,
+) -> Result<OrderConfirmation> {
+    MensaClient::new()?
+        .order(date, md5, mensa_id, user, time, lang)
+        .await
+}
 
-    params.insert("basket_html".to_owned(), auflistung_html);
+/// Orders all meals in the basket for pickup in the slot starting at `time`.
+pub async fn order_basket(
+    mensa_id: i32,
+    basket: &Basket,
+    user: &UserProfile,
+    time: NaiveTime,
+    lang: Language,
+) -> Result<OrderConfirmation> {
+    MensaClient::new()?
+        .order_basket(mensa_id, basket, user, time, lang)
+        .await
+}
 
-    let bf = format!("basket_full[{}]", a_id);
-    params.insert(bf.clone() + "[id]", a_id);
-    params.insert(bf.clone() + "[category]", meal.category);
-    params.insert(
-        bf.clone() + "[title]",
-        format!("{} {} {}", title, meal.description, meal.kennz_rest),
-    );
-    params.insert(bf.clone() + "[preis1]", meal.preis1);
-    params.insert(bf.clone() + "[preis2]", meal.preis2);
-    params.insert(bf.clone() + "[preis3]", meal.preis3);
-    params.insert(bf + "[anzahl]", "1".to_owned());
+/// Validates an order and builds the request without submitting it, see
+/// [`MensaClient::prepare_order`].
+pub async fn prepare_order(
+    date: NaiveDate,
+    md5: &str,
+    mensa_id: i32,
+    user: &UserProfile,
+    time: NaiveTime,
+    lang: Language,
+) -> Result<PreparedOrder> {
+    MensaClient::new()?
+        .prepare_order(date, md5, mensa_id, user, time, lang)
+        .await
+}
 
-    let response: String = client
-        .post("https://stwulm.my-mensa.de/setDataMensaTogo.php?order=add&language=de")
-        .form(&params)
-        .send()
-        .await?
-        .text()
-        .await?;
+pub async fn prepare_basket_order(
+    mensa_id: i32,
+    basket: &Basket,
+    user: &UserProfile,
+    time: NaiveTime,
+    lang: Language,
+) -> Result<PreparedOrder> {
+    MensaClient::new()?
+        .prepare_basket_order(mensa_id, basket, user, time, lang)
+        .await
+}
 
-    Ok(response)
+pub async fn submit_order(order: PreparedOrder) -> Result<OrderConfirmation> {
+    MensaClient::new()?.submit_order(order).await
 }
 
+/// Beginnings of the lowercase categories of side dishes and desserts
+const SIDE_CATEGORIES: &[&str] = &["beilage", "dessert", "nachtisch", "side"];
+
+#[derive(Clone, Debug)]
 pub struct MenuItem {
     pub category: String,
     pub name: String,
     pub combined_name: String,
     pub md5: String,
+    /// Article id used when ordering
+    pub article_id: String,
+    pub prices: Prices,
+    /// Price when ordering to go, in cents
+    pub togo_price: Option<u32>,
+    /// Raw allergen and additive labelling codes (`kennzRest`)
+    pub labels: String,
+    pub allergens: BTreeSet<Allergen>,
+    pub additives: BTreeSet<Additive>,
+    /// `None` if the meal could not be classified
+    pub diet: Option<Diet>,
 }
 
-pub struct DayMenu {
-    pub date: String,
-    pub meals: Vec<MenuItem>,
-}
-
-async fn get_menu_impl(mensa_id: i32) -> Result<(Arc<CookieStoreMutex>, Data)> {
-    let cookie_store = Arc::new(CookieStoreMutex::new(CookieStore::default()));
-
-    let client = reqwest::Client::builder()
-        .cookie_provider(Arc::clone(&cookie_store))
-        .build()
-        .context("Creating HTTP client failed")?;
-
-    let now = SystemTime::now();
-    let since_the_epoch = now.duration_since(UNIX_EPOCH).unwrap();
-    let now_millis = since_the_epoch.as_millis();
-
-    let url: String = format!("{API_BASE_URL}/getdata.php?mensa_id={mensa_id}&json=1&hyp=1&now={now_millis}&mode=togo&lang=de");
-    log::trace!("Calling API url: {}", &url);
-
-    let result = client
-        .get(url.as_str())
-        .send()
-        .await
-        .context("Failed to make HTTP request")?;
-    log::trace!("Response: {:?}", &result);
-
-    let response_text = result.text().await?;
-    log::trace!("Text: {:?}", response_text);
+impl MenuItem {
+    fn from_meal(meal: Meal, classifier: &DietClassifier) -> Self {
+        let Labels {
+            allergens,
+            additives,
+        } = Labels::parse(&meal.kennz_rest);
+
+        let mut item = MenuItem {
+            name: format!("{} {}", meal.title_clean, meal.description_clean),
+            combined_name: format!(
+                "{}: {}{}",
+                meal.category, meal.title_clean, meal.description_clean
+            ),
+            category: meal.category,
+            md5: meal.md5,
+            article_id: meal.attributes.artikel_id,
+            prices: Prices {
+                student: parse_price(&meal.preis1),
+                staff: parse_price(&meal.preis2),
+                guest: parse_price(&meal.preis3),
+            },
+            togo_price: parse_price(&meal.preis_formated_togo),
+            labels: meal.kennz_rest,
+            allergens,
+            additives,
+            diet: None,
+        };
+        item.diet = classifier.classify(&item);
+        item
+    }
 
-    let json: Data =
-        serde_json::from_str(&response_text).context("Failed to decode getdata response")?;
+    /// Whether the meal is neither a side dish nor a dessert, judged by its category.
+    pub fn is_main_dish(&self) -> bool {
+        let category = self.category.trim().to_lowercase();
+        !SIDE_CATEGORIES.iter().any(|c| category.starts_with(c))
+    }
 
-    debug!("Session cookies: {:?}", cookie_store.lock().unwrap());
+    /// Unclassified meals are considered unsuitable for any diet but [`Diet::Meat`].
+    pub fn is_suitable_for(&self, diet: Diet) -> bool {
+        match self.diet {
+            Some(d) => d.is_suitable_for(diet),
+            None => diet == Diet::Meat,
+        }
+    }
+}
 
-    Ok((Arc::clone(&cookie_store), json))
+#[cfg(test)]
+impl MenuItem {
+    /// Main dish without prices and labels, with `name` as md5
+    pub(crate) fn named(name: &str) -> MenuItem {
+        MenuItem {
+            category: "Hauptgericht".to_owned(),
+            name: name.to_owned(),
+            combined_name: format!("Hauptgericht: {}", name),
+            md5: name.to_owned(),
+            article_id: String::new(),
+            prices: Default::default(),
+            togo_price: None,
+            labels: String::new(),
+            allergens: Default::default(),
+            additives: Default::default(),
+            diet: None,
+        }
+    }
 }
 
-pub async fn get_menu(mensa_id: i32) -> Result<Vec<DayMenu>> {
-    let (_, data) = get_menu_impl(mensa_id).await?;
+#[derive(Clone, Debug)]
+pub struct DayMenu {
+    pub date: NaiveDate,
+    pub meals: Vec<MenuItem>,
+}
 
-    Ok(data
-        .result
-        .into_iter()
-        .map(|day| DayMenu {
-            date: day.tag.datum_iso,
-            meals: day
-                .essen
-                .into_iter()
-                .map(|meal| MenuItem {
-                    category: meal.category.clone(),
-                    name: format!("{} {}", meal.title_clean, meal.description_clean),
-                    combined_name: format!(
-                        "{}: {}{}",
-                        meal.category, meal.title_clean, meal.description_clean
-                    ),
-                    md5: meal.md5,
-                })
-                .collect(),
-        })
-        .collect())
+pub async fn get_menu(mensa_id: i32, lang: Language) -> Result<Vec<DayMenu>> {
+    MensaClient::new()?.get_menu(mensa_id, lang).await
 }
@@ -0,0 +1,61 @@
+use std::{fmt, time::Duration};
+
+/// Id of Mensa West at Universität Ulm, the canteen used when none is selected.
+pub const DEFAULT_MENSA_ID: i32 = 2;
+
+/// Highest id probed by [`MensaClient::list_mensas`](crate::MensaClient::list_mensas) by
+/// default. The my-mensa API has no endpoint listing the canteens, so their ids are probed.
+/// Mensa West has id 2, and the other canteens of the Studierendenwerk Ulm are assumed to have
+/// small ids as well. Set a different limit with
+/// [`MensaClientBuilder::max_mensa_id`](crate::MensaClientBuilder::max_mensa_id).
+pub(crate) const DEFAULT_MAX_MENSA_ID: i32 = 10;
+
+/// How long the result of [`MensaClient::list_mensas`](crate::MensaClient::list_mensas) is
+/// reused by default. Canteens rarely change, and listing them fetches a menu per id.
+pub(crate) const DEFAULT_MENSA_LIST_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Locations of the canteens, which the server does not report. Only known ones are listed.
+const LOCATIONS: &[(i32, &str)] = &[(2, "Universität Ulm, Campus West")];
+
+/// A canteen of the my-mensa instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mensa {
+    /// Id used by all API calls
+    pub id: i32,
+    /// Name as reported by the server
+    pub name: String,
+    pub location: Option<String>,
+    /// Whether meals can be ordered for pickup
+    pub togo: bool,
+}
+
+impl Mensa {
+    pub(crate) fn new(id: i32, name: String, togo: bool) -> Mensa {
+        Mensa {
+            id,
+            name,
+            location: LOCATIONS
+                .iter()
+                .find(|(i, _)| *i == id)
+                .map(|(_, l)| (*l).to_owned()),
+            togo,
+        }
+    }
+
+    /// Whether `query` is the id of this canteen or a word of its name, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        query == self.id.to_string()
+            || self.name.eq_ignore_ascii_case(query)
+            || self
+                .name
+                .split_whitespace()
+                .any(|word| word.eq_ignore_ascii_case(query))
+    }
+}
+
+impl fmt::Display for Mensa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.id)
+    }
+}
@@ -0,0 +1,26 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::NaiveDate;
+use reqwest_cookie_store::CookieStoreMutex;
+
+use crate::{Language, TimeSlot};
+
+/// Validated order with all form parameters for `setDataMensaTogo.php`, not yet submitted.
+///
+/// Created by [`MensaClient::prepare_order`](crate::MensaClient::prepare_order), sent with
+/// [`MensaClient::submit_order`](crate::MensaClient::submit_order).
+#[derive(Debug)]
+pub struct PreparedOrder {
+    pub mensa_id: i32,
+    pub date: NaiveDate,
+    /// Pickup slot, resolved from the free slots at preparation time
+    pub slot: TimeSlot,
+    /// Total to-go price in cents
+    pub total: Option<u32>,
+    /// Language of the menu the order was validated against
+    pub language: Language,
+    /// Form parameters (`client[...]`, `basket_*`, `basket_html`)
+    pub params: BTreeMap<String, String>,
+    /// Session established while fetching the menu, the order has to be sent within it
+    pub(crate) session: Arc<CookieStoreMutex>,
+}
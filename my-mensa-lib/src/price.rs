@@ -0,0 +1,159 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Prices of a meal in cents for each price group, `None` if the server did not provide one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Prices {
+    /// `preis1`
+    pub student: Option<u32>,
+    /// `preis2`
+    pub staff: Option<u32>,
+    /// `preis3`
+    pub guest: Option<u32>,
+}
+
+impl Prices {
+    /// Price for customers of `group`
+    pub fn get(&self, group: PriceGroup) -> Option<u32> {
+        match group {
+            PriceGroup::Student => self.student,
+            PriceGroup::Staff => self.staff,
+            PriceGroup::Guest => self.guest,
+        }
+    }
+}
+
+/// Which of the [`Prices`] a customer pays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceGroup {
+    #[default]
+    Student,
+    Staff,
+    Guest,
+}
+
+impl PriceGroup {
+    pub const ALL: [PriceGroup; 3] = [PriceGroup::Student, PriceGroup::Staff, PriceGroup::Guest];
+
+    pub fn name_de(self) -> &'static str {
+        match self {
+            PriceGroup::Student => "Studierende",
+            PriceGroup::Staff => "Beschäftigte",
+            PriceGroup::Guest => "Gäste",
+        }
+    }
+
+    pub fn name_en(self) -> &'static str {
+        match self {
+            PriceGroup::Student => "student",
+            PriceGroup::Staff => "staff",
+            PriceGroup::Guest => "guest",
+        }
+    }
+}
+
+impl fmt::Display for PriceGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name_en())
+    }
+}
+
+/// Parses the English or German name.
+impl FromStr for PriceGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|g| g.name_en().eq_ignore_ascii_case(s) || g.name_de().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown price group: {}", s))
+    }
+}
+
+/// Parses a price as sent by the server (e.g. `"3,80"`, `"3.80"` or `"3,80 €"`) into cents.
+pub fn parse_price(price: &str) -> Option<u32> {
+    let price: String = price
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+        .collect();
+
+    let (euros, cents) = match price.split_once([',', '.']) {
+        Some((euros, cents)) => (euros, cents),
+        None => (price.as_str(), ""),
+    };
+
+    if euros.is_empty() && cents.is_empty() {
+        return None;
+    }
+
+    let euros: u32 = if euros.is_empty() {
+        0
+    } else {
+        euros.parse().ok()?
+    };
+
+    let cents: u32 = match cents.len() {
+        0 => 0,
+        1 => cents.parse::<u32>().ok()? * 10,
+        _ => cents[..2].parse().ok()?,
+    };
+
+    euros.checked_mul(100)?.checked_add(cents)
+}
+
+/// Formats an amount in cents the way the mensa displays it, e.g. `"3,80 €"`.
+pub fn format_price(cents: u32) -> String {
+    format!("{},{:02} €", cents / 100, cents % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_are_parsed_into_cents() {
+        assert_eq!(parse_price("3,80"), Some(380));
+        assert_eq!(parse_price("3.80"), Some(380));
+        assert_eq!(parse_price("3,80 €"), Some(380));
+        assert_eq!(parse_price("€ 12,05"), Some(1205));
+        assert_eq!(parse_price("0,00"), Some(0));
+    }
+
+    #[test]
+    fn missing_cents_and_euros_are_zero() {
+        assert_eq!(parse_price("4"), Some(400));
+        assert_eq!(parse_price("4,"), Some(400));
+        assert_eq!(parse_price("3,8"), Some(380));
+        assert_eq!(parse_price(",50"), Some(50));
+    }
+
+    #[test]
+    fn extra_digits_are_cut_off() {
+        assert_eq!(parse_price("3,805"), Some(380));
+    }
+
+    #[test]
+    fn prices_without_digits_are_none() {
+        assert_eq!(parse_price(""), None);
+        assert_eq!(parse_price(" "), None);
+        assert_eq!(parse_price("€"), None);
+        assert_eq!(parse_price("-"), None);
+        assert_eq!(parse_price(","), None);
+    }
+
+    #[test]
+    fn malformed_prices_are_none() {
+        assert_eq!(parse_price("3,8,0"), None);
+        assert_eq!(parse_price("99999999999,00"), None);
+        assert_eq!(parse_price("50000000,00"), None);
+    }
+
+    #[test]
+    fn formatted_prices_parse_back() {
+        for cents in [0, 5, 99, 380, 1205] {
+            assert_eq!(parse_price(&format_price(cents)), Some(cents));
+        }
+    }
+}
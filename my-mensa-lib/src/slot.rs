@@ -0,0 +1,128 @@
+use std::fmt;
+
+use chrono::NaiveTime;
+
+use crate::{MensaError, Result};
+
+/// Pickup time slot, as returned by `get_free_slots`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSlot {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Number of orders that can still be placed for this slot
+    pub capacity: i32,
+}
+
+impl TimeSlot {
+    /// Parses a slot label like `"11:30 - 11:45"` as used as key in the `get_free_slots`
+    /// response. A label without end time is treated as a slot of zero length, one ending
+    /// before its start is invalid.
+    pub(crate) fn parse(label: &str, capacity: i32) -> Result<TimeSlot> {
+        let invalid = || MensaError::InvalidTimeSlot {
+            slot: label.to_owned(),
+        };
+
+        let (start, end) = match label.split_once('-') {
+            Some((start, end)) => (start, Some(end)),
+            None => (label, None),
+        };
+        let start = parse_time(start).ok_or_else(invalid)?;
+        let end = match end {
+            Some(end) => parse_time(end).ok_or_else(invalid)?,
+            None => start,
+        };
+        if end < start {
+            return Err(invalid());
+        }
+
+        Ok(TimeSlot {
+            start,
+            end,
+            capacity,
+        })
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.capacity > 0
+    }
+}
+
+impl fmt::Display for TimeSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    let s = s.trim();
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn labels_are_parsed() {
+        let slot = TimeSlot::parse("11:30 - 11:45", 3).unwrap();
+        assert_eq!(slot.start, time(11, 30));
+        assert_eq!(slot.end, time(11, 45));
+        assert_eq!(slot.capacity, 3);
+
+        let slot = TimeSlot::parse("11:30-11:45", 0).unwrap();
+        assert_eq!((slot.start, slot.end), (time(11, 30), time(11, 45)));
+        assert!(!slot.is_free());
+
+        let slot = TimeSlot::parse(" 11:30:00 - 11:45:00 ", 1).unwrap();
+        assert_eq!((slot.start, slot.end), (time(11, 30), time(11, 45)));
+    }
+
+    #[test]
+    fn label_without_end_has_zero_length() {
+        let slot = TimeSlot::parse("12:00", 1).unwrap();
+        assert_eq!((slot.start, slot.end), (time(12, 0), time(12, 0)));
+    }
+
+    #[test]
+    fn malformed_labels_are_rejected() {
+        for label in [
+            "",
+            "-",
+            "11:30 -",
+            "- 11:45",
+            "11:30 - 11:45 - 12:00",
+            "1130 - 1145",
+            "25:00 - 25:15",
+            "11:60 - 12:15",
+            "mittags",
+            "12:00 - 11:45",
+        ] {
+            match TimeSlot::parse(label, 1) {
+                Err(MensaError::InvalidTimeSlot { slot }) => assert_eq!(slot, label),
+                other => panic!("{:?} parsed as {:?}", label, other),
+            }
+        }
+    }
+
+    #[test]
+    fn negative_capacity_is_not_free() {
+        assert!(!TimeSlot::parse("11:30 - 11:45", -1).unwrap().is_free());
+    }
+
+    #[test]
+    fn slots_are_displayed_as_labels() {
+        let slot = TimeSlot::parse("9:05 - 9:20", 1).unwrap();
+        assert_eq!(slot.to_string(), "09:05 - 09:20");
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+use crate::TimeSlot;
+
+/// Change of a pickup slot between two polls of
+/// [`MensaClient::watch_slots`](crate::MensaClient::watch_slots).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotChange {
+    /// The slot has free capacity again, or appeared with free capacity
+    Opened(TimeSlot),
+    /// The last free place of the slot was taken, or the slot disappeared
+    Filled(TimeSlot),
+    /// The free capacity changed, but the slot stayed free
+    CapacityChanged { slot: TimeSlot, previous: i32 },
+}
+
+impl SlotChange {
+    /// The slot with its current capacity
+    pub fn slot(&self) -> TimeSlot {
+        match *self {
+            SlotChange::Opened(slot)
+            | SlotChange::Filled(slot)
+            | SlotChange::CapacityChanged { slot, .. } => slot,
+        }
+    }
+}
+
+impl fmt::Display for SlotChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotChange::Opened(slot) => write!(f, "{} opened ({} free)", slot, slot.capacity),
+            SlotChange::Filled(slot) => write!(f, "{} filled", slot),
+            SlotChange::CapacityChanged { slot, previous } => {
+                write!(f, "{} {} -> {} free", slot, previous, slot.capacity)
+            }
+        }
+    }
+}
+
+/// Changes from `previous` to `current`, in the order of `current`. Slots are identified by
+/// their start and end time.
+pub(crate) fn slot_changes(previous: &[TimeSlot], current: &[TimeSlot]) -> Vec<SlotChange> {
+    let same = |a: &TimeSlot, b: &TimeSlot| a.start == b.start && a.end == b.end;
+
+    let mut changes: Vec<SlotChange> = current
+        .iter()
+        .filter_map(|slot| {
+            let before = previous.iter().find(|p| same(p, slot));
+            match before {
+                None if slot.is_free() => Some(SlotChange::Opened(*slot)),
+                None => None,
+                Some(p) if p.capacity == slot.capacity => None,
+                Some(p) if !p.is_free() && slot.is_free() => Some(SlotChange::Opened(*slot)),
+                Some(p) if p.is_free() && !slot.is_free() => Some(SlotChange::Filled(*slot)),
+                Some(_) if !slot.is_free() => None,
+                Some(p) => Some(SlotChange::CapacityChanged {
+                    slot: *slot,
+                    previous: p.capacity,
+                }),
+            }
+        })
+        .collect();
+
+    changes.extend(
+        previous
+            .iter()
+            .filter(|p| p.is_free() && !current.iter().any(|slot| same(p, slot)))
+            .map(|p| SlotChange::Filled(TimeSlot { capacity: 0, ..*p })),
+    );
+
+    changes
+}
@@ -0,0 +1,544 @@
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveTime};
+use futures_util::StreamExt;
+use my_mensa_lib::{Basket, Language, MensaClient, MensaError, SlotChange, UserProfile};
+use my_mensa_mock::{Endpoint, Failure, Fixtures, MockServer};
+
+const MENSA_ID: i32 = 2;
+const SPAETZLE: &str = "4b1f2a6c9d0e8f7a1b2c3d4e5f607182";
+const CURRY: &str = "0f1e2d3c4b5a69788796a5b4c3d2e1f0";
+
+fn date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2023, 5, 8).unwrap()
+}
+
+fn time(hour: u32, min: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+}
+
+fn user() -> UserProfile {
+    UserProfile::new(
+        "Max".to_owned(),
+        "Mustermann".to_owned(),
+        "max.mustermann@uni-ulm.de".to_owned(),
+    )
+}
+
+async fn setup() -> (MockServer, MensaClient) {
+    let server = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let client = MensaClient::builder()
+        .api_base_url(server.api_base_url())
+        .togo_api_url(server.togo_api_url())
+        .retry_backoff(Duration::from_millis(1))
+        .build()
+        .unwrap();
+    (server, client)
+}
+
+#[tokio::test]
+async fn menu_is_parsed() {
+    let (_server, client) = setup().await;
+
+    let menu = client.get_menu(MENSA_ID, Language::German).await.unwrap();
+
+    assert_eq!(menu.len(), 2);
+    assert_eq!(menu[0].date, date());
+    let spaetzle = menu[0].meals.iter().find(|m| m.md5 == SPAETZLE).unwrap();
+    assert_eq!(spaetzle.prices.student, Some(380));
+    assert_eq!(spaetzle.togo_price, Some(380));
+}
+
+#[tokio::test]
+async fn english_menu_is_parsed() {
+    let (server, client) = setup().await;
+
+    let menu = client.get_menu(MENSA_ID, Language::English).await.unwrap();
+
+    let spaetzle = menu[0].meals.iter().find(|m| m.md5 == SPAETZLE).unwrap();
+    assert!(
+        spaetzle.name.starts_with("Cheese spaetzle"),
+        "{}",
+        spaetzle.name
+    );
+    // The pudding has no translation and is taken from the German menu
+    let pudding = menu[0]
+        .meals
+        .iter()
+        .find(|m| m.md5 == "ffeeddccbbaa00998877665544332211")
+        .unwrap();
+    assert_eq!(pudding.name.trim(), "Schokoladenpudding");
+    // The dal is missing from the English menu and added from the German one
+    assert!(menu[1]
+        .meals
+        .iter()
+        .any(|m| m.name.starts_with("Linsen-Dal")));
+    assert_eq!(menu[1].meals.len(), 2);
+    assert_eq!(server.requests(Endpoint::GetData), 2);
+
+    // The German menu fetched for the fallback is cached
+    client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 2);
+}
+
+#[tokio::test]
+async fn free_slots_are_parsed() {
+    let (_server, client) = setup().await;
+
+    let slots = client
+        .get_free_slots(MENSA_ID, &user().email, date())
+        .await
+        .unwrap();
+
+    assert_eq!(slots.len(), 6);
+    assert_eq!(slots[0].start, time(11, 30));
+    assert_eq!(slots[0].end, time(11, 45));
+    assert!(!slots[2].is_free());
+}
+
+#[tokio::test]
+async fn order_takes_a_slot() {
+    let (server, client) = setup().await;
+
+    let confirmation = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(12, 15),
+            Language::German,
+        )
+        .await
+        .unwrap();
+
+    assert!(confirmation.order_number.is_some());
+    assert_eq!(confirmation.slot.start, time(12, 15));
+    assert_eq!(server.capacity(date(), "12:15 - 12:30"), Some(0));
+    assert_eq!(server.orders().len(), 1);
+}
+
+#[tokio::test]
+async fn orders_use_their_own_session() {
+    let (server, client) = setup().await;
+    client.get_menu(MENSA_ID, Language::German).await.unwrap();
+
+    for slot in [time(11, 30), time(11, 45)] {
+        client
+            .order(date(), SPAETZLE, MENSA_ID, &user(), slot, Language::German)
+            .await
+            .unwrap();
+    }
+
+    // The cached menu is not used for ordering, each order fetches it in a new session
+    assert_eq!(server.requests(Endpoint::GetData), 3);
+    let orders = server.orders();
+    assert_eq!(orders.len(), 2);
+    assert_ne!(orders[0].session, orders[1].session);
+}
+
+#[tokio::test]
+async fn basket_order_sends_all_positions() {
+    let (server, client) = setup().await;
+    let menu = client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    let meal = |md5| menu[0].meals.iter().find(|m| m.md5 == md5).unwrap();
+    let mut basket = Basket::new(date());
+    basket
+        .add(meal(SPAETZLE), 1)
+        .unwrap()
+        .add(meal(CURRY), 1)
+        .unwrap()
+        .add(meal(SPAETZLE), 1)
+        .unwrap();
+
+    let confirmation = client
+        .order_basket(MENSA_ID, &basket, &user(), time(11, 30), Language::German)
+        .await
+        .unwrap();
+
+    assert_eq!(confirmation.total, Some(2 * 380 + 350));
+    let orders = server.orders();
+    assert_eq!(orders.len(), 1);
+    let param = |key: &str| orders[0].params.get(key).map(String::as_str);
+    assert_eq!(param("client[date_iso]"), Some("2023-05-08"));
+    assert_eq!(param("client[deliver_time_val]"), Some("11:30"));
+    assert_eq!(param("client[email]"), Some("max.mustermann@uni-ulm.de"));
+    assert_eq!(
+        orders[0]
+            .params
+            .keys()
+            .filter(|k| k.starts_with("basket_positions["))
+            .collect::<Vec<_>>(),
+        ["basket_positions[1001]", "basket_positions[1003]"]
+    );
+    assert_eq!(param("basket_positions[1001]"), Some("2"));
+    assert_eq!(param("basket_positions[1003]"), Some("1"));
+    assert_eq!(param("basket_full[1001][anzahl]"), Some("2"));
+    assert_eq!(param("basket_full[1003][id]"), Some("1003"));
+    assert_eq!(param("basket_full[1003][preis1]"), Some("3,50"));
+    assert!(param("basket_full[1003][title]")
+        .unwrap()
+        .starts_with("Gemüsecurry mit Basmatireis"));
+    let html = param("basket_html").unwrap();
+    assert!(html.contains("<td>2x</td> <td aid_check=\"1001\">Käsespätzle</td>"));
+    assert!(html.contains("<td>1x</td> <td aid_check=\"1003\">Gemüsecurry</td>"));
+    // One order takes one place in the slot
+    assert_eq!(server.capacity(date(), "11:30 - 11:45"), Some(4));
+}
+
+#[tokio::test]
+async fn full_slot_is_refused() {
+    let (server, client) = setup().await;
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(12, 0),
+            Language::German,
+        )
+        .await;
+
+    assert!(matches!(result, Err(MensaError::SlotFull { .. })));
+    assert!(server.orders().is_empty());
+}
+
+#[tokio::test]
+async fn outage_is_retried() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::GetData, Failure::Status(502));
+    server.fail_next(Endpoint::GetData, Failure::Status(503));
+
+    let menu = client.get_menu(MENSA_ID, Language::German).await.unwrap();
+
+    assert_eq!(menu.len(), 2);
+    assert_eq!(server.requests(Endpoint::GetData), 3);
+}
+
+#[tokio::test]
+async fn persistent_outage_is_reported() {
+    let (server, client) = setup().await;
+    for _ in 0..3 {
+        server.fail_next(Endpoint::GetData, Failure::Status(503));
+    }
+
+    let result = client.get_menu(MENSA_ID, Language::German).await;
+
+    assert!(matches!(
+        result,
+        Err(MensaError::ServerUnavailable {
+            endpoint: "getdata",
+            status: 503,
+            ..
+        })
+    ));
+    assert_eq!(server.requests(Endpoint::GetData), 3);
+}
+
+#[tokio::test]
+async fn html_instead_of_json_is_retried() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::GetData, Failure::Html);
+
+    let menu = client.get_menu(MENSA_ID, Language::German).await.unwrap();
+
+    assert_eq!(menu.len(), 2);
+    assert_eq!(server.requests(Endpoint::GetData), 2);
+}
+
+#[tokio::test]
+async fn persistent_html_is_reported_as_outage() {
+    let (server, client) = setup().await;
+    for _ in 0..3 {
+        server.fail_next(Endpoint::GetData, Failure::Html);
+    }
+
+    let result = client.get_menu(MENSA_ID, Language::German).await;
+
+    assert!(matches!(
+        result,
+        Err(MensaError::ServerUnavailable {
+            endpoint: "getdata",
+            status: 200,
+            ..
+        })
+    ));
+    assert_eq!(server.requests(Endpoint::GetData), 3);
+}
+
+#[tokio::test]
+async fn failed_ids_are_skipped_when_listing() {
+    let server = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let client = MensaClient::builder()
+        .api_base_url(server.api_base_url())
+        .togo_api_url(server.togo_api_url())
+        .retries(0)
+        .build()
+        .unwrap();
+    // Cached, so the failure hits another id
+    client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    server.fail_next(Endpoint::GetData, Failure::Status(500));
+
+    let mensas = client.list_mensas().await.unwrap();
+
+    assert_eq!(mensas.len(), 1);
+    assert_eq!(mensas[0].id, MENSA_ID);
+    assert_eq!(server.requests(Endpoint::GetData), 10);
+
+    // The incomplete list is not reused, only the failed id is fetched again
+    client.list_mensas().await.unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 11);
+    client.list_mensas().await.unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 11);
+}
+
+#[tokio::test]
+async fn client_error_is_not_retried() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::FreeSlots, Failure::Status(404));
+
+    let result = client.get_free_slots(MENSA_ID, &user().email, date()).await;
+
+    assert!(matches!(
+        result,
+        Err(MensaError::ServerUnavailable { status: 404, .. })
+    ));
+    assert_eq!(server.requests(Endpoint::FreeSlots), 1);
+}
+
+#[tokio::test]
+async fn order_is_not_retried() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::Order, Failure::Status(503));
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(11, 30),
+            Language::German,
+        )
+        .await;
+
+    assert!(matches!(result, Err(MensaError::OrderRejected { .. })));
+    assert_eq!(server.requests(Endpoint::Order), 1);
+    assert!(server.orders().is_empty());
+}
+
+#[tokio::test]
+async fn rejected_order() {
+    let (server, client) = setup().await;
+    server.fail_next(
+        Endpoint::Order,
+        Failure::Reject("Küche geschlossen".to_owned()),
+    );
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(11, 30),
+            Language::German,
+        )
+        .await;
+
+    match result {
+        Err(MensaError::OrderRejected { reason, .. }) => assert_eq!(reason, "Küche geschlossen"),
+        other => panic!("Expected OrderRejected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn unrecognised_order_response_is_unconfirmed() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::Order, Failure::Html);
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(11, 30),
+            Language::German,
+        )
+        .await;
+
+    assert!(matches!(result, Err(MensaError::OrderUnconfirmed { .. })));
+    assert_eq!(server.requests(Endpoint::Order), 1);
+}
+
+#[tokio::test]
+async fn order_timeout_is_unconfirmed() {
+    let server = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let client = MensaClient::builder()
+        .api_base_url(server.api_base_url())
+        .togo_api_url(server.togo_api_url())
+        .timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    server.fail_next(Endpoint::Order, Failure::Delay(Duration::from_secs(1)));
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(11, 30),
+            Language::German,
+        )
+        .await;
+
+    assert!(matches!(result, Err(MensaError::OrderUnconfirmed { .. })));
+    assert_eq!(server.requests(Endpoint::Order), 1);
+}
+
+#[tokio::test]
+async fn order_gateway_timeout_is_unconfirmed() {
+    let (server, client) = setup().await;
+    server.fail_next(Endpoint::Order, Failure::Status(504));
+
+    let result = client
+        .order(
+            date(),
+            SPAETZLE,
+            MENSA_ID,
+            &user(),
+            time(11, 30),
+            Language::German,
+        )
+        .await;
+
+    assert!(matches!(result, Err(MensaError::OrderUnconfirmed { .. })));
+}
+
+#[tokio::test]
+async fn menu_is_cached() {
+    let (server, client) = setup().await;
+
+    client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 1);
+
+    client
+        .refresh_menu(MENSA_ID, Language::German)
+        .await
+        .unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 2);
+}
+
+#[tokio::test]
+async fn menu_cache_is_persisted() {
+    let (server, _) = setup().await;
+    let file = std::env::temp_dir().join(format!("menu-cache-{}.json", std::process::id()));
+    let client = || {
+        MensaClient::builder()
+            .api_base_url(server.api_base_url())
+            .togo_api_url(server.togo_api_url())
+            .menu_cache_file(&file)
+            .build()
+            .unwrap()
+    };
+
+    client().get_menu(MENSA_ID, Language::German).await.unwrap();
+    // A new client, e.g. after a restart, reads the menu from the file
+    let restarted = client();
+    let menu = restarted
+        .get_menu(MENSA_ID, Language::German)
+        .await
+        .unwrap();
+    assert_eq!(menu[0].date, date());
+    assert_eq!(server.requests(Endpoint::GetData), 1);
+
+    restarted.clear_menu_cache().await;
+    client().get_menu(MENSA_ID, Language::German).await.unwrap();
+    assert_eq!(server.requests(Endpoint::GetData), 2);
+
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[tokio::test]
+async fn mensas_are_listed() {
+    let (server, client) = setup().await;
+
+    let mensas = client.list_mensas().await.unwrap();
+
+    assert_eq!(mensas.len(), 1);
+    assert_eq!(mensas[0].id, MENSA_ID);
+    assert_eq!(mensas[0].name, "Mensa West");
+    assert!(mensas[0].togo);
+    assert_eq!(server.requests(Endpoint::GetData), 10);
+
+    // The list is reused, even after the menus expired
+    client.clear_menu_cache().await;
+    assert_eq!(client.list_mensas().await.unwrap(), mensas);
+    assert_eq!(server.requests(Endpoint::GetData), 10);
+
+    assert_eq!(client.find_mensa("west").await.unwrap().id, MENSA_ID);
+    assert!(matches!(
+        client.find_mensa("nord").await,
+        Err(MensaError::MensaNotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn slot_changes_are_watched() {
+    let (server, client) = setup().await;
+    let changes = client.watch_slots(MENSA_ID, &user().email, date(), Duration::from_millis(10));
+    futures_util::pin_mut!(changes);
+
+    for _ in 0..5 {
+        let change = changes.next().await.unwrap().unwrap();
+        assert!(matches!(change, SlotChange::Opened(_)));
+    }
+
+    server.set_capacity(date(), "12:00 - 12:15", 2);
+    let change = changes.next().await.unwrap().unwrap();
+    assert_eq!(change.slot().start, time(12, 0));
+    assert!(matches!(change, SlotChange::Opened(slot) if slot.capacity == 2));
+
+    server.set_capacity(date(), "12:15 - 12:30", 0);
+    server.set_capacity(date(), "12:30 - 12:45", 7);
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(matches!(change, SlotChange::Filled(slot) if slot.start == time(12, 15)));
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(matches!(
+        change,
+        SlotChange::CapacityChanged { slot, previous: 8 } if slot.capacity == 7
+    ));
+}
+
+/// Runs against fixtures recorded with `uulm_mensa_cli record-fixtures <DIR>`, set
+/// `MY_MENSA_FIXTURES=<DIR>` and pass `--ignored`.
+#[tokio::test]
+#[ignore = "needs recorded fixtures in MY_MENSA_FIXTURES"]
+async fn recorded_fixtures_are_parsed() {
+    let dir = std::env::var("MY_MENSA_FIXTURES").expect("MY_MENSA_FIXTURES is not set");
+    let fixtures = Fixtures::from_dir(dir).unwrap();
+    let date = fixtures.dates()[0];
+    let server = MockServer::start(fixtures).await.unwrap();
+    let client = MensaClient::builder()
+        .api_base_url(server.api_base_url())
+        .togo_api_url(server.togo_api_url())
+        .build()
+        .unwrap();
+
+    let german = client.get_menu(MENSA_ID, Language::German).await.unwrap();
+    assert!(german.iter().any(|day| !day.meals.is_empty()));
+    let english = client.get_menu(MENSA_ID, Language::English).await.unwrap();
+    for (german, english) in german.iter().zip(&english) {
+        assert_eq!(german.date, english.date);
+        assert!(english.meals.iter().all(|m| !m.name.trim().is_empty()));
+    }
+    client
+        .get_free_slots(MENSA_ID, &user().email, date)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,521 @@
+//! Local emulation of the my-mensa endpoints used by `my-mensa-lib`, serving synthetic fixtures.
+//!
+//! The bundled fixtures are written by hand after the shape of the real responses, not
+//! recorded from the server. Use [`Fixtures::from_dir`] to serve responses recorded with
+//! `uulm_mensa_cli record-fixtures` instead.
+//!
+//! Point a `MensaClient` at [`MockServer::api_base_url`] and [`MockServer::togo_api_url`].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use hyper::{
+    body::to_bytes,
+    header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use linked_hash_map::LinkedHashMap;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+const GETDATA_FIXTURE: &str = include_str!("../fixtures/getdata.json");
+const GETDATA_EN_FIXTURE: &str = include_str!("../fixtures/getdata_en.json");
+const FREE_SLOTS_FIXTURE: &str = include_str!("../fixtures/free_slots.json");
+
+/// Path of the to-go API on the mock server, in place of the token path of the real server.
+const TOGO_API_PATH: &str = "/togo/api";
+
+const SESSION_COOKIE: &str = "PHPSESSID";
+
+/// Menu and slot data served by the [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct Fixtures {
+    /// `getdata.php` response in German, also served for languages without a translation
+    getdata: Value,
+    /// `getdata.php` responses by language code, which may lack the translation of some meals
+    translations: HashMap<String, Value>,
+    /// Initial capacity of each slot, the same for every day
+    free_slots: Vec<(String, i32)>,
+    /// The only mensa id answered with a menu
+    mensa_id: i32,
+}
+
+impl Fixtures {
+    /// The hand-written fixtures bundled with this crate, with an English menu.
+    pub fn synthetic() -> Fixtures {
+        Self::from_json(GETDATA_FIXTURE, FREE_SLOTS_FIXTURE)
+            .and_then(|f| f.with_translation("en", GETDATA_EN_FIXTURE))
+            .expect("Bundled fixtures are valid")
+    }
+
+    /// Loads `getdata.json` and `free_slots.json` from `dir`, and translations from
+    /// `getdata_<lang>.json` (e.g. `getdata_en.json`) if present.
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Fixtures> {
+        let dir = dir.as_ref();
+        let getdata = std::fs::read_to_string(dir.join("getdata.json"))?;
+        let free_slots = std::fs::read_to_string(dir.join("free_slots.json"))?;
+        let mut fixtures = Self::from_json(&getdata, &free_slots)?;
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let lang = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("getdata_")?.strip_suffix(".json"))
+                .map(str::to_owned);
+            if let Some(lang) = lang {
+                let getdata = std::fs::read_to_string(&path)?;
+                fixtures = fixtures.with_translation(&lang, &getdata)?;
+            }
+        }
+        Ok(fixtures)
+    }
+
+    pub fn from_json(getdata: &str, free_slots: &str) -> serde_json::Result<Fixtures> {
+        let free_slots: LinkedHashMap<String, i32> = serde_json::from_str(free_slots)?;
+        Ok(Fixtures {
+            getdata: serde_json::from_str(getdata)?,
+            translations: HashMap::new(),
+            free_slots: free_slots.into_iter().collect(),
+            mensa_id: 2,
+        })
+    }
+
+    /// Serves `getdata` for requests with `lang`, e.g. `"en"`, instead of the German menu.
+    pub fn with_translation(mut self, lang: &str, getdata: &str) -> serde_json::Result<Fixtures> {
+        self.translations
+            .insert(lang.to_owned(), serde_json::from_str(getdata)?);
+        Ok(self)
+    }
+
+    /// Serves the menu for `mensa_id` instead of 2. Other ids get an empty menu without a
+    /// name.
+    pub fn with_mensa_id(mut self, mensa_id: i32) -> Fixtures {
+        self.mensa_id = mensa_id;
+        self
+    }
+
+    /// Moves all days of the menu so that the first one is `start`, keeping the distance
+    /// between days. Bundled or captured menus are in the past, this makes them orderable again.
+    pub fn starting_at(mut self, start: NaiveDate) -> Fixtures {
+        move_days(&mut self.getdata, start);
+        for getdata in self.translations.values_mut() {
+            move_days(getdata, start);
+        }
+        self
+    }
+
+    /// Menu served for the language code `lang`
+    fn getdata(&self, lang: Option<&str>) -> &Value {
+        lang.and_then(|lang| self.translations.get(lang))
+            .unwrap_or(&self.getdata)
+    }
+
+    /// Dates of all days in the menu.
+    pub fn dates(&self) -> Vec<NaiveDate> {
+        self.getdata["result"]
+            .as_array()
+            .map(|days| days.iter().filter_map(day_date).collect())
+            .unwrap_or_default()
+    }
+}
+
+fn move_days(getdata: &mut Value, start: NaiveDate) {
+    let days = match getdata["result"].as_array_mut() {
+        Some(days) => days,
+        None => return,
+    };
+    let first = match days.first().and_then(day_date) {
+        Some(first) => first,
+        None => return,
+    };
+
+    for day in days.iter_mut() {
+        if let Some(date) = day_date(day) {
+            let date = start + (date - first);
+            day["tag"]["datum_iso"] = json!(date.format("%Y-%m-%d").to_string());
+            day["tag"]["tag_formatiert2"] = json!(format!(
+                "{}, {}",
+                weekday_de(date.weekday()),
+                date.format("%d.%m.%Y")
+            ));
+        }
+    }
+}
+
+fn day_date(day: &Value) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(day["tag"]["datum_iso"].as_str()?, "%Y-%m-%d").ok()
+}
+
+fn weekday_de(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Montag",
+        Weekday::Tue => "Dienstag",
+        Weekday::Wed => "Mittwoch",
+        Weekday::Thu => "Donnerstag",
+        Weekday::Fri => "Freitag",
+        Weekday::Sat => "Samstag",
+        Weekday::Sun => "Sonntag",
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// `getdata.php`
+    GetData,
+    /// `get_free_slots/`
+    FreeSlots,
+    /// `setDataMensaTogo.php`
+    Order,
+}
+
+/// Misbehaviour injected into the next request to an [`Endpoint`].
+#[derive(Clone, Debug)]
+pub enum Failure {
+    /// Respond with this HTTP status and an HTML error page
+    Status(u16),
+    /// Respond with status 200, but an HTML page instead of JSON
+    Html,
+    /// Wait before handling the request normally
+    Delay(Duration),
+    /// Reject the order with this message
+    Reject(String),
+}
+
+/// Order received by the mock server.
+#[derive(Clone, Debug)]
+pub struct RecordedOrder {
+    pub order_number: u32,
+    /// Session cookie the order was sent with
+    pub session: String,
+    pub params: BTreeMap<String, String>,
+}
+
+struct State {
+    fixtures: Fixtures,
+    /// Remaining capacity per day and slot, initialized from the fixtures on first access
+    slots: HashMap<String, Vec<(String, i32)>>,
+    failures: HashMap<Endpoint, VecDeque<Failure>>,
+    requests: HashMap<Endpoint, usize>,
+    sessions: HashSet<String>,
+    orders: Vec<RecordedOrder>,
+    next_session: u32,
+    next_order_number: u32,
+}
+
+impl State {
+    fn slots(&mut self, date: &str) -> &mut Vec<(String, i32)> {
+        let initial = &self.fixtures.free_slots;
+        self.slots
+            .entry(date.to_owned())
+            .or_insert_with(|| initial.clone())
+    }
+}
+
+/// my-mensa emulation running in the background on the current Tokio runtime. Stops when
+/// dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockServer {
+    /// Starts a server on a random local port.
+    pub async fn start(fixtures: Fixtures) -> hyper::Result<MockServer> {
+        Self::bind(([127, 0, 0, 1], 0).into(), fixtures).await
+    }
+
+    pub async fn bind(addr: SocketAddr, fixtures: Fixtures) -> hyper::Result<MockServer> {
+        let state = Arc::new(Mutex::new(State {
+            fixtures,
+            slots: HashMap::new(),
+            failures: HashMap::new(),
+            requests: HashMap::new(),
+            sessions: HashSet::new(),
+            orders: Vec::new(),
+            next_session: 1,
+            next_order_number: 1001,
+        }));
+
+        let service_state = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let state = Arc::clone(&service_state);
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&state), req))) }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_service);
+        let addr = server.local_addr();
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            shutdown_rx.await.ok();
+        }));
+
+        log::info!("Mock my-mensa server listening on {}", addr);
+
+        Ok(MockServer {
+            addr,
+            state,
+            _shutdown: shutdown,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replacement for `DEFAULT_API_BASE_URL`
+    pub fn api_base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Replacement for `DEFAULT_TOGO_API_URL`
+    pub fn togo_api_url(&self) -> String {
+        format!("http://{}{}", self.addr, TOGO_API_PATH)
+    }
+
+    /// Makes the next request to `endpoint` fail. Multiple failures are used up in order.
+    pub fn fail_next(&self, endpoint: Endpoint, failure: Failure) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .entry(endpoint)
+            .or_default()
+            .push_back(failure);
+    }
+
+    /// Number of requests received by `endpoint`, including failed ones.
+    pub fn requests(&self, endpoint: Endpoint) -> usize {
+        let state = self.state.lock().unwrap();
+        state.requests.get(&endpoint).copied().unwrap_or(0)
+    }
+
+    /// All orders accepted so far.
+    pub fn orders(&self) -> Vec<RecordedOrder> {
+        self.state.lock().unwrap().orders.clone()
+    }
+
+    /// Remaining capacity of the slot with the given label (e.g. `"11:30 - 11:45"`).
+    pub fn capacity(&self, date: NaiveDate, slot: &str) -> Option<i32> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .slots(&date.format("%Y-%m-%d").to_string())
+            .iter()
+            .find(|(label, _)| label == slot)
+            .map(|(_, capacity)| *capacity)
+    }
+
+    pub fn set_capacity(&self, date: NaiveDate, slot: &str, capacity: i32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, c)) = state
+            .slots(&date.format("%Y-%m-%d").to_string())
+            .iter_mut()
+            .find(|(label, _)| label == slot)
+        {
+            *c = capacity;
+        }
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    log::debug!("{} {}", req.method(), req.uri());
+
+    let endpoint = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/getdata.php") => Endpoint::GetData,
+        (&Method::POST, p) if p.trim_end_matches('/') == "/togo/api/get_free_slots" => {
+            Endpoint::FreeSlots
+        }
+        (&Method::POST, "/setDataMensaTogo.php") => Endpoint::Order,
+        _ => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+
+    let failure = {
+        let mut state = state.lock().unwrap();
+        *state.requests.entry(endpoint).or_default() += 1;
+        state
+            .failures
+            .get_mut(&endpoint)
+            .and_then(VecDeque::pop_front)
+    };
+
+    match failure {
+        Some(Failure::Status(code)) => {
+            return Ok(status(
+                StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            ))
+        }
+        Some(Failure::Html) => return Ok(html(StatusCode::OK)),
+        Some(Failure::Delay(delay)) => tokio::time::sleep(delay).await,
+        Some(Failure::Reject(message)) => {
+            return Ok(json_response(
+                json!({ "status": "error", "message": message }),
+            ))
+        }
+        None => {}
+    }
+
+    let response = match endpoint {
+        Endpoint::GetData => {
+            let query: BTreeMap<String, String> =
+                form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+                    .into_owned()
+                    .collect();
+            let mensa_id = query.get("mensa_id").and_then(|v| v.parse().ok());
+            get_data(&state, mensa_id, query.get("lang").map(String::as_str))
+        }
+        Endpoint::FreeSlots => {
+            let params = form_params(req).await;
+            free_slots(&state, &params)
+        }
+        Endpoint::Order => {
+            let session = session_cookie(&req);
+            let params = form_params(req).await;
+            order(&state, session, params)
+        }
+    };
+
+    Ok(response)
+}
+
+fn get_data(state: &Mutex<State>, mensa_id: Option<i32>, lang: Option<&str>) -> Response<Body> {
+    let mut state = state.lock().unwrap();
+    if mensa_id != Some(state.fixtures.mensa_id) {
+        return json_response(json!({ "mensaname": "", "result": [] }));
+    }
+
+    let session = format!("mock{}", state.next_session);
+    state.next_session += 1;
+    state.sessions.insert(session.clone());
+
+    let mut response = json_response(state.fixtures.getdata(lang).clone());
+    response.headers_mut().insert(
+        SET_COOKIE,
+        format!("{}={}; path=/", SESSION_COOKIE, session)
+            .parse()
+            .unwrap(),
+    );
+    response
+}
+
+fn free_slots(state: &Mutex<State>, params: &BTreeMap<String, String>) -> Response<Body> {
+    let date = match params.get("tag") {
+        Some(date) => date,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+
+    let mut state = state.lock().unwrap();
+    let slots: serde_json::Map<String, Value> = state
+        .slots(date)
+        .iter()
+        .map(|(label, capacity)| (label.clone(), json!(capacity)))
+        .collect();
+
+    json_response(Value::Object(slots))
+}
+
+fn order(
+    state: &Mutex<State>,
+    session: Option<String>,
+    params: BTreeMap<String, String>,
+) -> Response<Body> {
+    let reject = |message: &str| json_response(json!({ "status": "error", "message": message }));
+
+    let mut state = state.lock().unwrap();
+
+    let session = match session.filter(|s| state.sessions.contains(s)) {
+        Some(session) => session,
+        None => return reject("Sitzung abgelaufen"),
+    };
+
+    let (date, time) = match (
+        params.get("client[date_iso]"),
+        params.get("client[deliver_time_val]"),
+    ) {
+        (Some(date), Some(time)) => (date.clone(), time.clone()),
+        _ => return reject("Unvollständige Bestellung"),
+    };
+
+    if !params.keys().any(|k| k.starts_with("basket_positions[")) {
+        return reject("Warenkorb ist leer");
+    }
+
+    let slot = match state
+        .slots(&date)
+        .iter_mut()
+        .find(|(label, _)| label.starts_with(&time))
+    {
+        Some(slot) => slot,
+        None => return reject("Unbekanntes Zeitfenster"),
+    };
+
+    if slot.1 <= 0 {
+        return reject("Zeitfenster ausgebucht");
+    }
+    slot.1 -= 1;
+
+    let order_number = state.next_order_number;
+    state.next_order_number += 1;
+    state.orders.push(RecordedOrder {
+        order_number,
+        session,
+        params,
+    });
+
+    json_response(json!({
+        "status": "ok",
+        "bestellnummer": order_number.to_string(),
+        "abholzeit": time,
+    }))
+}
+
+fn session_cookie(req: &Request<Body>) -> Option<String> {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_owned())
+}
+
+async fn form_params(req: Request<Body>) -> BTreeMap<String, String> {
+    let body = to_bytes(req.into_body()).await.unwrap_or_default();
+    form_urlencoded::parse(&body).into_owned().collect()
+}
+
+fn json_response(value: Value) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    html(code)
+}
+
+/// Error page as sent by the real server when overloaded
+fn html(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .header(CONTENT_TYPE, "text/html")
+        .body(Body::from(format!(
+            "<html><head><title>{0}</title></head><body><h1>{0}</h1></body></html>",
+            code
+        )))
+        .unwrap()
+}
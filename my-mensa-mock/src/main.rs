@@ -0,0 +1,54 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use chrono::Local;
+use clap::Parser;
+use my_mensa_mock::{Fixtures, MockServer};
+
+/// Offline my-mensa server for development and staging
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+
+    /// Directory containing getdata.json and free_slots.json, defaults to the bundled fixtures
+    #[arg(short, long)]
+    fixtures: Option<PathBuf>,
+
+    /// Move the menu so that its first day is today
+    #[arg(long)]
+    today: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+
+    let fixtures = match &cli.fixtures {
+        Some(dir) => Fixtures::from_dir(dir).unwrap_or_else(|e| {
+            eprintln!("Failed to load fixtures from {}: {e}", dir.display());
+            std::process::exit(1);
+        }),
+        None => Fixtures::synthetic(),
+    };
+    let fixtures = if cli.today {
+        fixtures.starting_at(Local::now().date_naive())
+    } else {
+        fixtures
+    };
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], cli.port));
+    let server = MockServer::bind(addr, fixtures).await.unwrap_or_else(|e| {
+        eprintln!("Failed to bind {addr}: {e}");
+        std::process::exit(1);
+    });
+
+    println!("MENSA_API_BASE_URL={}", server.api_base_url());
+    println!("MENSA_TOGO_API_URL={}", server.togo_api_url());
+
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen for ctrl-c");
+}
@@ -0,0 +1,378 @@
+//! Notifications about meals matching the keywords of a user.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDate};
+use my_mensa_lib::{DayMenu, Language, MensaClient};
+use sqlx::sqlite::SqlitePool;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{i18n::Texts, stored_profile, MyStorage};
+
+/// Time between two checks of the menus
+const CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Prefix of the callback data of the order button of an alert
+pub const ALERT_CALLBACK_PREFIX: &str = "alert:";
+
+/// Keywords of all users, persisted in the `alerts` table if a database is given. Every meal
+/// is only announced once per user, remembered in the `alerts_sent` table. Meals already on the
+/// menu when a keyword is added are remembered the same way, so only new meals are announced.
+pub struct Alerts {
+    bot: Bot,
+    client: MensaClient,
+    storage: MyStorage,
+    db: Option<SqlitePool>,
+    keywords: Mutex<Vec<(ChatId, String)>>,
+    /// Chat, date and md5 of the announced or already published meals
+    sent: Mutex<HashSet<(ChatId, NaiveDate, String)>>,
+}
+
+impl Alerts {
+    /// Loads the keywords from `db` and starts checking the menus.
+    pub async fn open(
+        bot: Bot,
+        client: MensaClient,
+        storage: MyStorage,
+        db: Option<SqlitePool>,
+    ) -> Result<Arc<Alerts>, sqlx::Error> {
+        let mut keywords = vec![];
+        let mut sent = HashSet::new();
+        if let Some(db) = &db {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS alerts (
+                    chat_id BIGINT NOT NULL,
+                    keyword TEXT NOT NULL,
+                    PRIMARY KEY (chat_id, keyword)
+                )",
+            )
+            .execute(db)
+            .await?;
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS alerts_sent (
+                    chat_id BIGINT NOT NULL,
+                    date TEXT NOT NULL,
+                    md5 TEXT NOT NULL,
+                    PRIMARY KEY (chat_id, date, md5)
+                )",
+            )
+            .execute(db)
+            .await?;
+
+            let rows: Vec<(i64, String)> = sqlx::query_as("SELECT chat_id, keyword FROM alerts")
+                .fetch_all(db)
+                .await?;
+            keywords = rows
+                .into_iter()
+                .map(|(chat_id, keyword)| (ChatId(chat_id), keyword))
+                .collect();
+
+            let rows: Vec<(i64, String, String)> =
+                sqlx::query_as("SELECT chat_id, date, md5 FROM alerts_sent")
+                    .fetch_all(db)
+                    .await?;
+            sent = rows
+                .into_iter()
+                .filter_map(|(chat_id, date, md5)| Some((ChatId(chat_id), date.parse().ok()?, md5)))
+                .collect();
+        }
+
+        let alerts = Arc::new(Alerts {
+            bot,
+            client,
+            storage,
+            db,
+            keywords: Mutex::new(keywords),
+            sent: Mutex::new(sent),
+        });
+        tokio::spawn(alerts.clone().run());
+
+        Ok(alerts)
+    }
+
+    /// Keywords of the chat, in the order they were added
+    pub fn keywords(&self, chat_id: ChatId) -> Vec<String> {
+        let keywords = self.keywords.lock().unwrap();
+        keywords
+            .iter()
+            .filter(|(c, _)| *c == chat_id)
+            .map(|(_, k)| k.clone())
+            .collect()
+    }
+
+    /// Adds a keyword, returns `false` if the chat already watches it. Matching meals on the
+    /// current menu of the mensa are not announced.
+    pub async fn watch(
+        &self,
+        chat_id: ChatId,
+        keyword: &str,
+        (mensa_id, language): (i32, Language),
+    ) -> Result<bool, sqlx::Error> {
+        if self.find(chat_id, keyword).is_some() {
+            return Ok(false);
+        }
+
+        let today = Local::now().date_naive();
+        let keywords = [keyword.to_lowercase()];
+        match self.client.get_menu(mensa_id, language).await {
+            Ok(menu) => {
+                for day in menu.iter().filter(|day| day.date >= today) {
+                    for meal in day
+                        .meals
+                        .iter()
+                        .filter(|m| matches(&m.combined_name, &keywords))
+                    {
+                        self.mark_sent(chat_id, day.date, &meal.md5).await;
+                    }
+                }
+            }
+            Err(e) => log::warn!("Failed to get menu of mensa {}: {}", mensa_id, e),
+        }
+
+        if let Some(db) = &self.db {
+            sqlx::query("INSERT OR REPLACE INTO alerts VALUES (?, ?)")
+                .bind(chat_id.0)
+                .bind(keyword)
+                .execute(db)
+                .await?;
+        }
+
+        self.keywords
+            .lock()
+            .unwrap()
+            .push((chat_id, keyword.to_owned()));
+        Ok(true)
+    }
+
+    /// Removes a keyword, ignoring case. Returns `false` if the chat did not watch it.
+    pub async fn unwatch(&self, chat_id: ChatId, keyword: &str) -> Result<bool, sqlx::Error> {
+        let keyword = match self.find(chat_id, keyword) {
+            Some(keyword) => keyword,
+            None => return Ok(false),
+        };
+
+        if let Some(db) = &self.db {
+            sqlx::query("DELETE FROM alerts WHERE chat_id = ? AND keyword = ?")
+                .bind(chat_id.0)
+                .bind(&keyword)
+                .execute(db)
+                .await?;
+        }
+
+        self.keywords
+            .lock()
+            .unwrap()
+            .retain(|(c, k)| !(*c == chat_id && *k == keyword));
+        Ok(true)
+    }
+
+    /// Date and md5 of the meals announced to the chat
+    pub fn sent(&self, chat_id: ChatId) -> Vec<(NaiveDate, String)> {
+        let mut sent: Vec<_> = self
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(c, _, _)| *c == chat_id)
+            .map(|(_, date, md5)| (*date, md5.clone()))
+            .collect();
+        sent.sort();
+        sent
+    }
+
+    /// Removes the keywords and announced meals of the chat.
+    pub async fn forget_chat(&self, chat_id: ChatId) -> Result<(), sqlx::Error> {
+        if let Some(db) = &self.db {
+            for table in ["alerts", "alerts_sent"] {
+                sqlx::query(&format!("DELETE FROM {} WHERE chat_id = ?", table))
+                    .bind(chat_id.0)
+                    .execute(db)
+                    .await?;
+            }
+        }
+
+        self.keywords.lock().unwrap().retain(|(c, _)| *c != chat_id);
+        self.sent.lock().unwrap().retain(|(c, _, _)| *c != chat_id);
+        Ok(())
+    }
+
+    /// Stored spelling of a keyword of the chat
+    fn find(&self, chat_id: ChatId, keyword: &str) -> Option<String> {
+        let keyword = keyword.to_lowercase();
+        self.keywords(chat_id)
+            .into_iter()
+            .find(|k| k.to_lowercase() == keyword)
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check().await;
+        }
+    }
+
+    /// Announces all meals from today on matching a keyword that were not announced yet.
+    async fn check(&self) {
+        let today = Local::now().date_naive();
+        self.forget_before(today).await;
+
+        let mut by_chat: HashMap<ChatId, Vec<String>> = HashMap::new();
+        for (chat_id, keyword) in self.keywords.lock().unwrap().iter() {
+            by_chat
+                .entry(*chat_id)
+                .or_default()
+                .push(keyword.to_lowercase());
+        }
+
+        let mut menus: HashMap<(i32, Language), Vec<DayMenu>> = HashMap::new();
+        for (chat_id, keywords) in by_chat {
+            let user = match stored_profile(&self.storage, chat_id).await {
+                Some(user) => user,
+                None => continue,
+            };
+            let menu = match menus.entry((user.mensa_id, user.language)) {
+                Entry::Occupied(menu) => menu.into_mut(),
+                Entry::Vacant(entry) => {
+                    match self.client.get_menu(user.mensa_id, user.language).await {
+                        Ok(menu) => entry.insert(menu),
+                        Err(e) => {
+                            log::warn!("Failed to get menu of mensa {}: {}", user.mensa_id, e);
+                            continue;
+                        }
+                    }
+                }
+            };
+            let texts = Texts::new(user.language);
+
+            for day in menu.iter().filter(|day| day.date >= today) {
+                for meal in &day.meals {
+                    if !matches(&meal.combined_name, &keywords) {
+                        continue;
+                    }
+                    if !self.mark_sent(chat_id, day.date, &meal.md5).await {
+                        continue;
+                    }
+
+                    let button = InlineKeyboardButton::callback(
+                        texts.alert_order_button(),
+                        format!("{}{}:{}", ALERT_CALLBACK_PREFIX, day.date, meal.md5),
+                    );
+                    let result = self
+                        .bot
+                        .send_message(chat_id, texts.meal_alert(&meal.combined_name, day.date))
+                        .reply_markup(InlineKeyboardMarkup::new(vec![vec![button]]))
+                        .await;
+                    if let Err(e) = result {
+                        log::warn!("Failed to send alert to {}: {}", chat_id, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remembers an announced meal, returns `false` if it was announced before.
+    async fn mark_sent(&self, chat_id: ChatId, date: NaiveDate, md5: &str) -> bool {
+        if !self
+            .sent
+            .lock()
+            .unwrap()
+            .insert((chat_id, date, md5.to_owned()))
+        {
+            return false;
+        }
+
+        if let Some(db) = &self.db {
+            let result = sqlx::query("INSERT OR REPLACE INTO alerts_sent VALUES (?, ?, ?)")
+                .bind(chat_id.0)
+                .bind(date.to_string())
+                .bind(md5)
+                .execute(db)
+                .await;
+            if let Err(e) = result {
+                log::warn!("Failed to store sent alert: {}", e);
+            }
+        }
+        true
+    }
+
+    async fn forget_before(&self, date: NaiveDate) {
+        self.sent.lock().unwrap().retain(|(_, d, _)| *d >= date);
+
+        if let Some(db) = &self.db {
+            let result = sqlx::query("DELETE FROM alerts_sent WHERE date < ?")
+                .bind(date.to_string())
+                .execute(db)
+                .await;
+            if let Err(e) = result {
+                log::warn!("Failed to remove old alerts: {}", e);
+            }
+        }
+    }
+}
+
+/// Whether the meal name contains any of the lowercase keywords, ignoring case
+fn matches(name: &str, keywords: &[String]) -> bool {
+    let name = name.to_lowercase();
+    keywords.iter().any(|k| name.contains(k.as_str()))
+}
+
+/// Date and meal md5 of an alert order button
+pub fn parse_alert(data: &str) -> Option<(NaiveDate, String)> {
+    let (date, md5) = data.strip_prefix(ALERT_CALLBACK_PREFIX)?.split_once(':')?;
+    Some((date.parse().ok()?, md5.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_match_ignoring_case() {
+        let keywords = ["spätzle".to_owned(), "curry".to_owned()];
+
+        assert!(matches("Hauptgericht: Käsespätzle mit Salat", &keywords));
+        assert!(matches("Vegan: Gemüse-CURRY", &keywords));
+        assert!(matches("KÄSESPÄTZLE", &keywords));
+        assert!(!matches("Hauptgericht: Schweineschnitzel", &keywords));
+        assert!(!matches("Hauptgericht: Käsespätzle", &[]));
+    }
+
+    #[test]
+    fn alert_buttons_are_parsed() {
+        let data = format!(
+            "{}2023-05-08:4b1f2a6c9d0e8f7a1b2c3d4e5f607182",
+            ALERT_CALLBACK_PREFIX
+        );
+
+        assert_eq!(
+            parse_alert(&data),
+            Some((
+                NaiveDate::from_ymd_opt(2023, 5, 8).unwrap(),
+                "4b1f2a6c9d0e8f7a1b2c3d4e5f607182".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn malformed_alert_buttons_are_rejected() {
+        for data in [
+            "",
+            "alert:",
+            "alert:2023-05-08",
+            "alert:08.05.2023:abc",
+            "alert:2023-13-01:abc",
+            "freed:2023-05-08:abc",
+            "2023-05-08:abc",
+        ] {
+            assert_eq!(parse_alert(data), None, "{}", data);
+        }
+    }
+}
@@ -0,0 +1,915 @@
+//! German and English texts of the bot.
+
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveTime, Weekday};
+use my_mensa_lib::{
+    format_price, Allergen, Language, MensaError, OrderConfirmation, PriceGroup, TimeSlot,
+    UserProfile,
+};
+use teloxide::types::{BotCommand, User};
+
+use crate::ProfileField;
+
+/// Language of the Telegram client, German for `de` and its variants, otherwise English.
+pub fn telegram_language(user: Option<&User>) -> Language {
+    match user.and_then(|u| u.language_code.as_deref()) {
+        Some(code) if code.starts_with("de") => Language::German,
+        _ => Language::English,
+    }
+}
+
+/// Message catalog for one language.
+#[derive(Clone, Copy, Debug)]
+pub struct Texts(Language);
+
+impl Texts {
+    pub fn new(language: Language) -> Texts {
+        Texts(language)
+    }
+
+    fn de(self) -> bool {
+        self.0 == Language::German
+    }
+
+    fn pick(self, de: &'static str, en: &'static str) -> &'static str {
+        if self.de() {
+            de
+        } else {
+            en
+        }
+    }
+
+    pub fn date(self, date: NaiveDate) -> String {
+        if self.de() {
+            date.format("%d.%m.%Y").to_string()
+        } else {
+            date.to_string()
+        }
+    }
+
+    /// Usage and description of all commands
+    fn commands(self) -> [(&'static str, &'static str); 14] {
+        if self.de() {
+            [
+                ("/help", "Diese Hilfe anzeigen"),
+                ("/start", "Die Einrichtung neu starten"),
+                (
+                    "/menu [vegan|vegetarisch|pescetarisch]",
+                    "Speiseplan der nächsten Tage anzeigen",
+                ),
+                ("/order [JJJJ-MM-TT]", "Essen bestellen"),
+                ("/cancel", "Bestellung abbrechen"),
+                ("/mensa", "Deine Mensa auswählen"),
+                ("/profile", "Deine Daten und Preisgruppe ändern"),
+                ("/language [de|en]", "Sprache ändern"),
+                (
+                    "/subscribe [HH:MM] [mo-fr]",
+                    "Jeden Tag den Speiseplan schicken",
+                ),
+                ("/unsubscribe", "Tägliche Speisepläne abbestellen"),
+                (
+                    "/watch [Stichwort]",
+                    "Bescheid bekommen, wenn es ein Lieblingsessen gibt",
+                ),
+                ("/unwatch <Stichwort>", "Stichwort entfernen"),
+                ("/mydata", "Alle über dich gespeicherten Daten anzeigen"),
+                ("/deleteme", "Alle deine Daten löschen"),
+            ]
+        } else {
+            [
+                ("/help", "Display this help text"),
+                ("/start", "Restart the welcome dialog"),
+                (
+                    "/menu [vegan|vegetarian|pescetarian]",
+                    "Show menu for the next days",
+                ),
+                ("/order [YYYY-MM-DD]", "Display order form"),
+                ("/cancel", "Cancel the order"),
+                ("/mensa", "Choose your mensa"),
+                ("/profile", "Change your details and price group"),
+                ("/language [de|en]", "Change the language"),
+                ("/subscribe [HH:MM] [mon-fri]", "Get the menu every day"),
+                ("/unsubscribe", "Stop the daily menu"),
+                ("/watch [keyword]", "Get notified about a favorite meal"),
+                ("/unwatch <keyword>", "Remove a keyword"),
+                ("/mydata", "Show all data stored about you"),
+                ("/deleteme", "Delete all your data"),
+            ]
+        }
+    }
+
+    /// Commands for `set_my_commands`
+    pub fn bot_commands(self) -> Vec<BotCommand> {
+        self.commands()
+            .iter()
+            .map(|(usage, description)| {
+                let command = usage.split_whitespace().next().unwrap_or(usage);
+                BotCommand::new(command.trim_start_matches('/'), *description)
+            })
+            .collect()
+    }
+
+    pub fn help(self) -> String {
+        let mut text = self
+            .pick(
+                "Diese Befehle werden unterstützt:",
+                "These commands are supported:",
+            )
+            .to_owned();
+        for (usage, description) in self.commands() {
+            text += &format!("\n{} – {}", usage, description);
+        }
+        text
+    }
+
+    pub fn ask_first_name(self) -> &'static str {
+        self.pick(
+            "Hallo! Bitte gib deinen Vornamen ein.",
+            "Hello! Please enter your first name.",
+        )
+    }
+
+    pub fn ask_last_name(self, first_name: &str) -> String {
+        if self.de() {
+            format!("Danke, {}! Bitte gib deinen Nachnamen ein.", first_name)
+        } else {
+            format!("Thanks, {}! Please enter your last name.", first_name)
+        }
+    }
+
+    pub fn ask_email(self, first_name: &str, last_name: &str) -> String {
+        if self.de() {
+            format!(
+                "Danke, {} {}! Bitte gib deine E-Mail-Adresse ein.",
+                first_name, last_name
+            )
+        } else {
+            format!(
+                "Thank you, {} {}! Please enter your email address.",
+                first_name, last_name
+            )
+        }
+    }
+
+    pub fn setup_complete(self, first_name: &str, last_name: &str, email: &str) -> String {
+        if self.de() {
+            format!(
+                "Die Einrichtung ist abgeschlossen! Falls etwas nicht stimmt, starte sie mit /start neu.\nVorname: \"{}\"\nNachname: \"{}\"\nE-Mail: \"{}\"",
+                first_name, last_name, email
+            )
+        } else {
+            format!(
+                "This completes the setup! If the following is incorrect, please restart the setup using /start.\nFirst name: \"{}\"\nLast name: \"{}\"\nEmail: \"{}\"",
+                first_name, last_name, email
+            )
+        }
+    }
+
+    pub fn invalid_email(self, email: &str) -> String {
+        if self.de() {
+            format!(
+                "\"{}\" ist keine gültige E-Mail-Adresse. Bitte gib sie in der Form name@example.org ein.",
+                email
+            )
+        } else {
+            format!(
+                "\"{}\" is not a valid email address. Please enter it like name@example.org.",
+                email
+            )
+        }
+    }
+
+    pub fn verification_sent(self, email: &str) -> String {
+        if self.de() {
+            format!(
+                "Wir haben dir einen Bestätigungscode an {} geschickt. Bitte sende ihn hier zurück. Ist die Adresse falsch, gib einfach die richtige ein.",
+                email
+            )
+        } else {
+            format!(
+                "We sent a verification code to {}. Please send it back here. If the address is wrong, just enter the correct one.",
+                email
+            )
+        }
+    }
+
+    pub fn verification_mail_subject(self) -> &'static str {
+        self.pick("Dein Bestätigungscode", "Your verification code")
+    }
+
+    pub fn verification_mail_body(self, first_name: &str, code: &str) -> String {
+        if self.de() {
+            format!(
+                "Hallo {},\n\ndein Bestätigungscode für den Mensa-Bot ist: {}\n\nFalls du den Bot nicht verwendest, kannst du diese E-Mail ignorieren.",
+                first_name, code
+            )
+        } else {
+            format!(
+                "Hello {},\n\nyour verification code for the mensa bot is: {}\n\nIf you do not use the bot, you can ignore this email.",
+                first_name, code
+            )
+        }
+    }
+
+    pub fn mail_failed(self) -> &'static str {
+        self.pick(
+            "Die E-Mail konnte nicht gesendet werden. Bitte prüfe die Adresse und gib sie erneut ein.",
+            "The email could not be sent. Please check the address and enter it again.",
+        )
+    }
+
+    pub fn mail_limited(self, retry_after: Duration) -> String {
+        let minutes = retry_after.as_secs().div_ceil(60);
+        if self.de() {
+            format!(
+                "Du hast zu viele Bestätigungscodes angefordert. Bitte gib deine E-Mail-Adresse in {} Minuten erneut ein.",
+                minutes
+            )
+        } else {
+            format!(
+                "You requested too many verification codes. Please enter your email address again in {} minutes.",
+                minutes
+            )
+        }
+    }
+
+    pub fn wrong_code(self) -> &'static str {
+        self.pick(
+            "Dieser Code stimmt nicht. Bitte versuche es noch einmal.",
+            "This code is not correct. Please try again.",
+        )
+    }
+
+    pub fn too_many_attempts(self) -> &'static str {
+        self.pick(
+            "Zu viele falsche Codes. Bitte gib deine E-Mail-Adresse erneut ein.",
+            "Too many wrong codes. Please enter your email address again.",
+        )
+    }
+
+    pub fn internal_error(self) -> &'static str {
+        self.pick(
+            "Entschuldigung, im Bot ist ein Fehler aufgetreten! Die Einrichtung startet neu.",
+            "Sorry, an error has ocurred in the bot! Restarting dialogue.",
+        )
+    }
+
+    pub fn price_group(self, group: PriceGroup) -> &'static str {
+        if self.de() {
+            group.name_de()
+        } else {
+            match group {
+                PriceGroup::Student => "Students",
+                PriceGroup::Staff => "Staff",
+                PriceGroup::Guest => "Guests",
+            }
+        }
+    }
+
+    pub fn profile(self, contact: &UserProfile, price_group: PriceGroup) -> String {
+        if self.de() {
+            format!(
+                "Vorname: \"{}\"\nNachname: \"{}\"\nE-Mail: \"{}\"\nPreisgruppe: {}",
+                contact.firstname,
+                contact.lastname,
+                contact.email,
+                self.price_group(price_group)
+            )
+        } else {
+            format!(
+                "First name: \"{}\"\nLast name: \"{}\"\nEmail: \"{}\"\nPrice group: {}",
+                contact.firstname,
+                contact.lastname,
+                contact.email,
+                self.price_group(price_group)
+            )
+        }
+    }
+
+    pub fn profile_saved(self, contact: &UserProfile, price_group: PriceGroup) -> String {
+        format!(
+            "{}\n{}",
+            self.pick("Gespeichert!", "Saved!"),
+            self.profile(contact, price_group)
+        )
+    }
+
+    pub fn edit_field_button(self, field: ProfileField) -> &'static str {
+        match field {
+            ProfileField::FirstName => self.pick("Vorname ändern", "Change first name"),
+            ProfileField::LastName => self.pick("Nachname ändern", "Change last name"),
+            ProfileField::Email => self.pick("E-Mail ändern", "Change email"),
+        }
+    }
+
+    pub fn edit_price_group_button(self) -> &'static str {
+        self.pick("Preisgruppe ändern", "Change price group")
+    }
+
+    pub fn ask_field(self, field: ProfileField) -> &'static str {
+        match field {
+            ProfileField::FirstName => self.pick(
+                "Bitte gib deinen Vornamen ein.",
+                "Please enter your first name.",
+            ),
+            ProfileField::LastName => self.pick(
+                "Bitte gib deinen Nachnamen ein.",
+                "Please enter your last name.",
+            ),
+            ProfileField::Email => self.pick(
+                "Bitte gib deine E-Mail-Adresse ein.",
+                "Please enter your email address.",
+            ),
+        }
+    }
+
+    pub fn ask_price_group(self) -> &'static str {
+        self.pick(
+            "Welche Preise sollen angezeigt werden?",
+            "Which prices should be shown?",
+        )
+    }
+
+    pub fn ask_mensa(self) -> &'static str {
+        self.pick(
+            "In welche Mensa gehst du normalerweise?",
+            "Which mensa do you usually go to?",
+        )
+    }
+
+    /// Button label of a mensa without to-go ordering
+    pub fn mensa_without_ordering(self, name: &str) -> String {
+        if self.de() {
+            format!("{} (keine Bestellung)", name)
+        } else {
+            format!("{} (no ordering)", name)
+        }
+    }
+
+    pub fn mensas_unavailable(self) -> &'static str {
+        self.pick(
+            "Die Mensen konnten gerade nicht geladen werden. Wähle deine Mensa später mit /mensa.",
+            "The mensas could not be loaded right now. Choose your mensa later using /mensa.",
+        )
+    }
+
+    pub fn mensa_unavailable(self) -> &'static str {
+        self.pick(
+            "Diese Mensa ist nicht verfügbar.",
+            "This mensa is not available.",
+        )
+    }
+
+    pub fn mensa_selected(self, name: &str, togo: bool) -> String {
+        let mut text = if self.de() {
+            format!("Deine Mensa ist jetzt {}.", name)
+        } else {
+            format!("Your mensa is now {}.", name)
+        };
+        if !togo {
+            text += self.pick(
+                " Dort kann nicht bestellt werden, aber /menu zeigt trotzdem den Speiseplan.",
+                " Ordering is not available there, but you can still see the /menu.",
+            );
+        }
+        text
+    }
+
+    pub fn language_current(self) -> &'static str {
+        self.pick(
+            "Ich spreche Deutsch, auch der Speiseplan ist auf Deutsch. Mit /language en wechselst du zu Englisch.",
+            "The bot and the menu are in English. Use /language de to switch to German.",
+        )
+    }
+
+    pub fn language_selected(self) -> &'static str {
+        self.pick(
+            "Ab jetzt spreche ich Deutsch.",
+            "From now on, I will speak English.",
+        )
+    }
+
+    pub fn unknown_language(self, language: &str) -> String {
+        if self.de() {
+            format!("Unbekannte Sprache: {}", language)
+        } else {
+            format!("Unknown language: {}", language)
+        }
+    }
+
+    fn weekday(self, day: Weekday) -> &'static str {
+        const GERMAN: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+        const ENGLISH: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+        let i = day.num_days_from_monday() as usize;
+        self.pick(GERMAN[i], ENGLISH[i])
+    }
+
+    pub fn subscribed(self, time: NaiveTime, weekdays: &[Weekday]) -> String {
+        let days: Vec<_> = weekdays.iter().map(|d| self.weekday(*d)).collect();
+        let (time, days) = (time.format("%H:%M"), days.join(", "));
+        if self.de() {
+            format!(
+                "Ich schicke dir den Speiseplan um {} Uhr ({}). Abbestellen mit /unsubscribe.",
+                time, days
+            )
+        } else {
+            format!(
+                "I will send you the menu at {} ({}). Use /unsubscribe to stop.",
+                time, days
+            )
+        }
+    }
+
+    pub fn unsubscribed(self) -> &'static str {
+        self.pick(
+            "Du bekommst keine Speisepläne mehr.",
+            "You will no longer get the menu.",
+        )
+    }
+
+    pub fn not_subscribed(self) -> &'static str {
+        self.pick(
+            "Du hast den Speiseplan nicht abonniert.",
+            "You are not subscribed to the menu.",
+        )
+    }
+
+    pub fn invalid_schedule(self) -> &'static str {
+        self.pick(
+            "Bitte gib eine Uhrzeit und Wochentage an, z.B. /subscribe 11:00 mo-fr",
+            "Please give a time and weekdays, e.g. /subscribe 11:00 mon-fri",
+        )
+    }
+
+    /// Keywords of `/watch`
+    pub fn keywords(self, keywords: &[String]) -> String {
+        if keywords.is_empty() {
+            return self
+                .pick(
+                    "Du hast keine Stichwörter. Mit /watch Käsespätzle bekommst du Bescheid, wenn es Käsespätzle gibt.",
+                    "You have no keywords. Use /watch Schnitzel to get notified when there is Schnitzel.",
+                )
+                .to_owned();
+        }
+        let mut text = self.pick("Deine Stichwörter:", "Your keywords:").to_owned();
+        for keyword in keywords {
+            text += &format!("\n{}", keyword);
+        }
+        text
+    }
+
+    pub fn watching(self, keyword: &str) -> String {
+        if self.de() {
+            format!(
+                "Ich sage dir Bescheid, sobald \"{}\" neu auf dem Speiseplan steht.",
+                keyword
+            )
+        } else {
+            format!(
+                "I will tell you as soon as \"{}\" is added to the menu.",
+                keyword
+            )
+        }
+    }
+
+    pub fn already_watching(self, keyword: &str) -> String {
+        if self.de() {
+            format!("Du hast \"{}\" schon als Stichwort.", keyword)
+        } else {
+            format!("You are already watching \"{}\".", keyword)
+        }
+    }
+
+    pub fn not_watching_anymore(self, keyword: &str) -> String {
+        if self.de() {
+            format!("Das Stichwort \"{}\" wurde entfernt.", keyword)
+        } else {
+            format!("Removed the keyword \"{}\".", keyword)
+        }
+    }
+
+    pub fn not_watching(self, keyword: &str) -> String {
+        if self.de() {
+            format!("Du hast kein Stichwort \"{}\".", keyword)
+        } else {
+            format!("You are not watching \"{}\".", keyword)
+        }
+    }
+
+    pub fn meal_alert(self, meal: &str, date: NaiveDate) -> String {
+        if self.de() {
+            format!("Am {} gibt es {}!", self.date(date), meal)
+        } else {
+            format!("{} is on the menu on {}!", meal, self.date(date))
+        }
+    }
+
+    pub fn alert_order_button(self) -> &'static str {
+        self.pick("Bestellen", "Order")
+    }
+
+    pub fn unknown_diet(self, diet: &str) -> String {
+        if self.de() {
+            format!(
+                "Unbekannte Ernährungsform: {}. Möglich sind vegan, vegetarisch und pescetarisch.",
+                diet
+            )
+        } else {
+            format!(
+                "Unknown diet: {}. Use vegan, vegetarian or pescetarian.",
+                diet
+            )
+        }
+    }
+
+    pub fn invalid_date(self) -> &'static str {
+        self.pick(
+            "Ungültiges Datum, bitte im Format JJJJ-MM-TT angeben.",
+            "Invalid date, please use YYYY-MM-DD.",
+        )
+    }
+
+    pub fn no_order_date(self) -> &'static str {
+        self.pick(
+            "Für diesen Tag kann nicht bestellt werden.",
+            "Error finding correct order date...",
+        )
+    }
+
+    pub fn choose_meal(self, date: NaiveDate) -> String {
+        if self.de() {
+            format!("Gericht für {} auswählen", self.date(date))
+        } else {
+            format!("Choose Meal for {}", self.date(date))
+        }
+    }
+
+    pub fn no_free_slots(self) -> &'static str {
+        self.pick(
+            "Es sind keine Zeitfenster mehr frei!",
+            "No free slots are available!",
+        )
+    }
+
+    pub fn waitlist_offer(self) -> &'static str {
+        self.pick(
+            "Es sind keine Zeitfenster mehr frei! Soll ich dir Bescheid sagen, sobald eines frei wird?",
+            "No free slots are available! Should I tell you when one frees up?",
+        )
+    }
+
+    pub fn waitlist_notify_button(self) -> &'static str {
+        self.pick(
+            "Benachrichtigen, wenn etwas frei wird",
+            "Notify me when a slot frees up",
+        )
+    }
+
+    pub fn waitlist_auto_button(self, from: &str, to: &str) -> String {
+        if self.de() {
+            format!("Automatisch bestellen, {} - {}", from, to)
+        } else {
+            format!("Order automatically, {} - {}", from, to)
+        }
+    }
+
+    pub fn waitlist_added(self, meal: &str, window: Option<(NaiveTime, NaiveTime)>) -> String {
+        match window {
+            Some((from, to)) => {
+                let (from, to) = (from.format("%H:%M"), to.format("%H:%M"));
+                if self.de() {
+                    format!(
+                        "Sobald zwischen {} und {} ein Zeitfenster frei wird, bestelle ich {}.",
+                        from, to, meal
+                    )
+                } else {
+                    format!(
+                        "As soon as a slot between {} and {} frees up, I will order {}.",
+                        from, to, meal
+                    )
+                }
+            }
+            None => {
+                if self.de() {
+                    format!(
+                        "Ich sage dir Bescheid, sobald für {} ein Zeitfenster frei wird.",
+                        meal
+                    )
+                } else {
+                    format!("I will tell you as soon as a slot for {} frees up.", meal)
+                }
+            }
+        }
+    }
+
+    pub fn slot_freed(self, meal: &str, date: NaiveDate) -> String {
+        if self.de() {
+            format!(
+                "Für {} am {} ist ein Zeitfenster frei geworden! Abholzeit auswählen:",
+                meal,
+                self.date(date)
+            )
+        } else {
+            format!(
+                "A slot for {} on {} has become free! Select Time Slot:",
+                meal,
+                self.date(date)
+            )
+        }
+    }
+
+    pub fn slot_freed_auto(self, meal: &str) -> String {
+        if self.de() {
+            format!("Für {} ist ein Zeitfenster frei geworden.", meal)
+        } else {
+            format!("A slot for {} has become free.", meal)
+        }
+    }
+
+    pub fn waitlist_expired(self, meal: &str, date: NaiveDate) -> String {
+        if self.de() {
+            format!(
+                "Für {} am {} ist leider kein Zeitfenster mehr frei geworden.",
+                meal,
+                self.date(date)
+            )
+        } else {
+            format!(
+                "Unfortunately, no slot for {} on {} became free.",
+                meal,
+                self.date(date)
+            )
+        }
+    }
+
+    pub fn select_slot(self) -> &'static str {
+        self.pick("Abholzeit auswählen", "Select Time Slot")
+    }
+
+    /// Button label of a time slot
+    pub fn slot_button(self, slot: &TimeSlot) -> String {
+        if self.de() {
+            format!("{} ({} frei)", slot, slot.capacity)
+        } else {
+            format!("{} ({} free)", slot, slot.capacity)
+        }
+    }
+
+    pub fn order_summary(
+        self,
+        meal: &str,
+        price: Option<u32>,
+        date: NaiveDate,
+        slot: &TimeSlot,
+        allergens: &[Allergen],
+    ) -> String {
+        let mut text = self
+            .pick(
+                "Bitte bestätige deine Bestellung:",
+                "Please confirm your order:",
+            )
+            .to_owned();
+        text += &format!("\n{}", meal);
+        if let Some(price) = price {
+            text += self.pick("\nPreis: ", "\nPrice: ");
+            text += &format_price(price);
+        }
+        text += self.pick("\nAbholung: ", "\nPickup: ");
+        text += &if self.de() {
+            format!("{} um {}", self.date(date), slot)
+        } else {
+            format!("{} at {}", self.date(date), slot)
+        };
+        if !allergens.is_empty() {
+            let names: Vec<_> = allergens
+                .iter()
+                .map(|a| if self.de() { a.name_de() } else { a.name_en() })
+                .collect();
+            text += self.pick("\nAchtung, enthält: ", "\nWarning, contains: ");
+            text += &names.join(", ");
+        }
+        text
+    }
+
+    pub fn confirm_button(self) -> &'static str {
+        self.pick("Bestellen", "Confirm")
+    }
+
+    pub fn change_slot_button(self) -> &'static str {
+        self.pick("Andere Zeit", "Change slot")
+    }
+
+    pub fn cancel_button(self) -> &'static str {
+        self.pick("Abbrechen", "Cancel")
+    }
+
+    pub fn order_cancelled(self) -> &'static str {
+        self.pick(
+            "Bestellung abgebrochen, es wurde nichts bestellt.",
+            "Order cancelled, nothing was ordered.",
+        )
+    }
+
+    pub fn order_expired(self) -> &'static str {
+        self.pick(
+            "Die Bestellung wurde abgebrochen, weil du länger nichts ausgewählt hast.",
+            "The order was cancelled because nothing was selected for a while.",
+        )
+    }
+
+    pub fn profile_unchanged(self) -> &'static str {
+        self.pick(
+            "Änderung abgebrochen, dein Profil ist unverändert.",
+            "Edit cancelled, your profile is unchanged.",
+        )
+    }
+
+    pub fn nothing_to_cancel(self) -> &'static str {
+        self.pick("Es gibt nichts abzubrechen.", "There is nothing to cancel.")
+    }
+
+    pub fn my_data(self) -> &'static str {
+        self.pick(
+            "Das sind alle Daten, die der Bot über diesen Chat gespeichert hat.",
+            "This is all data the bot stored about this chat.",
+        )
+    }
+
+    pub fn ask_delete_data(self) -> &'static str {
+        self.pick(
+            "Sollen dein Profil, deine Abos, Stichwörter und Wartelisten-Einträge gelöscht werden? Das kann nicht rückgängig gemacht werden.",
+            "Delete your profile, subscriptions, keywords and waitlist entries? This cannot be undone.",
+        )
+    }
+
+    pub fn delete_data_button(self) -> &'static str {
+        self.pick("Alles löschen", "Delete everything")
+    }
+
+    pub fn keep_data_button(self) -> &'static str {
+        self.pick("Behalten", "Keep")
+    }
+
+    pub fn data_deleted(self) -> &'static str {
+        self.pick(
+            "Alle deine Daten wurden gelöscht. Mit /start kannst du dich neu anmelden.",
+            "All your data was deleted. Use /start to register again.",
+        )
+    }
+
+    pub fn data_kept(self) -> &'static str {
+        self.pick("Es wurde nichts gelöscht.", "Nothing was deleted.")
+    }
+
+    pub fn setup_cancelled(self) -> &'static str {
+        self.pick(
+            "Einrichtung abgebrochen. Mit /start fängst du neu an.",
+            "Setup cancelled. Use /start to begin again.",
+        )
+    }
+
+    pub fn command_unavailable(self) -> &'static str {
+        self.pick(
+            "Dieser Befehl ist gerade nicht verfügbar. Schließe zuerst den aktuellen Schritt ab oder brich ihn mit /cancel ab.",
+            "This command is not available right now. Finish the current step first, or /cancel it.",
+        )
+    }
+
+    pub fn not_understood(self) -> &'static str {
+        self.pick(
+            "Das habe ich nicht verstanden. /help zeigt alle Befehle.",
+            "I did not understand that. /help lists all commands.",
+        )
+    }
+
+    pub fn use_buttons(self) -> &'static str {
+        self.pick(
+            "Bitte wähle einen der Knöpfe aus oder brich mit /cancel ab.",
+            "Please use one of the buttons, or /cancel the order.",
+        )
+    }
+
+    pub fn ordering(self) -> &'static str {
+        self.pick("Bestellung wird abgeschickt...", "Ordering...")
+    }
+
+    pub fn ordered_staging(self, date: NaiveDate, slot: &TimeSlot) -> String {
+        if self.de() {
+            format!(
+                "Bestellt! (Staging, es wurde nichts abgeschickt) Abholung am {} um {}.",
+                self.date(date),
+                slot
+            )
+        } else {
+            format!(
+                "Ordered! (staging, nothing was submitted) Pickup on {} at {}.",
+                self.date(date),
+                slot
+            )
+        }
+    }
+
+    pub fn confirmation(self, confirmation: &OrderConfirmation) -> String {
+        let date = self.date(confirmation.date);
+        let mut text = if self.de() {
+            format!("Bestellt! Abholung am {} um {}.", date, confirmation.slot)
+        } else {
+            format!("Ordered! Pickup on {} at {}.", date, confirmation.slot)
+        };
+        if let Some(number) = &confirmation.order_number {
+            text += self.pick("\nBestellnummer: ", "\nOrder number: ");
+            text += number;
+        }
+        if let Some(total) = confirmation.total {
+            text += self.pick("\nSumme: ", "\nTotal: ");
+            text += &format_price(total);
+        }
+        text
+    }
+
+    pub fn order_unconfirmed(self) -> &'static str {
+        self.pick(
+            "Der Mensa-Server hat die Bestellung nicht bestätigt, sie wurde aber vielleicht trotzdem aufgenommen. Bitte sieh in deinen E-Mails nach einer Bestellbestätigung, bevor du noch einmal bestellst.",
+            "The mensa server did not confirm the order, but it may have been placed anyway. Please check your email for an order confirmation before ordering again.",
+        )
+    }
+
+    pub fn order_error(self, e: &MensaError) -> String {
+        let reason = match e {
+            // Not a failure, the order may have been placed
+            MensaError::OrderUnconfirmed { .. } => return self.order_unconfirmed().to_owned(),
+            MensaError::DayNotFound { date } => {
+                if self.de() {
+                    format!(
+                        "Der Speiseplan für {} ist nicht mehr verfügbar.",
+                        self.date(*date)
+                    )
+                } else {
+                    format!("The menu for {} is no longer available.", self.date(*date))
+                }
+            }
+            MensaError::MealNotFound { .. } => self
+                .pick(
+                    "Dieses Gericht steht nicht mehr auf dem Speiseplan.",
+                    "This meal is no longer on the menu.",
+                )
+                .to_owned(),
+            MensaError::SlotNotFound { time } => {
+                let time = time.format("%H:%M");
+                if self.de() {
+                    format!("Das Zeitfenster {} gibt es nicht.", time)
+                } else {
+                    format!("The time slot {} does not exist.", time)
+                }
+            }
+            MensaError::SlotFull { slot } => {
+                if self.de() {
+                    format!(
+                        "Das Zeitfenster {} ist schon voll, bitte wähle ein anderes.",
+                        slot
+                    )
+                } else {
+                    format!(
+                        "The time slot {} is already full, please try another one.",
+                        slot
+                    )
+                }
+            }
+            MensaError::Http(_)
+            | MensaError::ServerUnavailable { .. }
+            | MensaError::Decode { .. }
+            | MensaError::InvalidTimeSlot { .. } => self
+                .pick(
+                    "Der Mensa-Server ist nicht erreichbar, bitte versuche es später noch einmal.",
+                    "The mensa server is not reachable, please try again later.",
+                )
+                .to_owned(),
+            MensaError::EmptyBasket => self
+                .pick("Kein Gericht ausgewählt.", "No meal selected.")
+                .to_owned(),
+            MensaError::InvalidQuantity { .. } => self
+                .pick("Ungültige Anzahl.", "Invalid quantity.")
+                .to_owned(),
+            MensaError::MensaNotFound { .. } | MensaError::AmbiguousMensa { .. } => self
+                .pick(
+                    "Die Mensa ist nicht verfügbar.",
+                    "The mensa is not available.",
+                )
+                .to_owned(),
+            MensaError::OrderRejected { reason, .. } => {
+                if self.de() {
+                    format!("Der Mensa-Server hat die Bestellung abgelehnt: {}", reason)
+                } else {
+                    format!("The order was rejected by the mensa server: {}", reason)
+                }
+            }
+        };
+
+        format!(
+            "{} {}",
+            self.pick("Bestellung fehlgeschlagen:", "Ordering failed:"),
+            reason
+        )
+    }
+}
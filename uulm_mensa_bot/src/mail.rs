@@ -0,0 +1,522 @@
+//! Email address validation and verification mails.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use teloxide::types::ChatId;
+
+/// Whether `email` looks like a deliverable address: one `@`, no whitespace, and a domain
+/// with at least two non-empty labels.
+pub fn is_valid_email(email: &str) -> bool {
+    let (local, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let labels: Vec<&str> = domain.split('.').collect();
+
+    !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        && !email.contains(['<', '>', ',', ';', '"'])
+        && labels.len() >= 2
+        && labels
+            .iter()
+            .all(|l| !l.is_empty() && !l.starts_with('-') && !l.ends_with('-') && !l.contains('@'))
+        && labels.last().is_some_and(|tld| tld.len() >= 2)
+}
+
+/// Random six digit code
+pub fn verification_code() -> String {
+    let mut bytes = [0; 4];
+    getrandom::getrandom(&mut bytes).expect("No random source available");
+    format!("{:06}", u32::from_le_bytes(bytes) % 1_000_000)
+}
+
+/// Sends plain text mails.
+pub trait MailTransport: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        to: &'a str,
+        subject: &'a str,
+        body: &'a str,
+    ) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Verification mails, `None` if email addresses are not verified
+pub type Mailer = Option<Arc<VerificationMailer>>;
+
+/// Minimum time between two verification mails of a chat
+const MAIL_COOLDOWN: Duration = Duration::from_secs(60);
+/// Verification mails a chat can trigger within [`MAIL_LIMIT_WINDOW`]
+const MAX_MAILS_PER_WINDOW: usize = 5;
+const MAIL_LIMIT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Why a verification mail was not sent
+#[derive(Debug)]
+pub enum SendError {
+    /// The chat requested too many mails, the next one can be sent after `retry_after`
+    Limited {
+        retry_after: Duration,
+    },
+    Failed(io::Error),
+}
+
+/// Sends verification mails, at most one per minute and five per day for each chat, so that
+/// the bot cannot be used to flood arbitrary addresses.
+pub struct VerificationMailer {
+    transport: Box<dyn MailTransport>,
+    /// Times of the mails of each chat within the last [`MAIL_LIMIT_WINDOW`]
+    sent: Mutex<HashMap<ChatId, VecDeque<Instant>>>,
+}
+
+impl VerificationMailer {
+    pub fn new(transport: impl MailTransport + 'static) -> VerificationMailer {
+        VerificationMailer {
+            transport: Box::new(transport),
+            sent: Mutex::default(),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        chat_id: ChatId,
+        to: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), SendError> {
+        self.reserve(chat_id, Instant::now())
+            .map_err(|retry_after| SendError::Limited { retry_after })?;
+        self.transport
+            .send(to, subject, body)
+            .await
+            .map_err(SendError::Failed)
+    }
+
+    /// Counts a mail of `chat_id` at `now`, or returns the time until the chat may send the
+    /// next one. Failed deliveries count as well.
+    fn reserve(&self, chat_id: ChatId, now: Instant) -> Result<(), Duration> {
+        let mut sent = self.sent.lock().unwrap();
+        sent.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= MAIL_LIMIT_WINDOW)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = sent.entry(chat_id).or_default();
+        if let Some(last) = times.back() {
+            let since = now.duration_since(*last);
+            if since < MAIL_COOLDOWN {
+                return Err(MAIL_COOLDOWN - since);
+            }
+        }
+        if let (true, Some(first)) = (times.len() >= MAX_MAILS_PER_WINDOW, times.front()) {
+            return Err(MAIL_LIMIT_WINDOW - now.duration_since(*first));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+/// Time for delivering a mail, including connecting
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the connection to the SMTP server is secured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// TLS from the start, port 465 by default
+    Tls,
+    /// Upgraded to TLS using STARTTLS, port 587 by default
+    StartTls,
+    /// Unencrypted, port 25 by default. Only for a relay on the same host or in the same
+    /// network, or a local SMTP sink for testing.
+    None,
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tls" => Ok(SmtpSecurity::Tls),
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "none" => Ok(SmtpSecurity::None),
+            _ => Err(format!("expected tls, starttls or none, got {:?}", s)),
+        }
+    }
+}
+
+/// Mails sent via an SMTP server, optionally with TLS and a login.
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    timeout: Duration,
+}
+
+impl SmtpTransport {
+    /// `server` is `host` or `host:port`, the port defaults to the one of `security`.
+    pub fn new(
+        server: &str,
+        from: &str,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+    ) -> io::Result<SmtpTransport> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+        let (host, port) = match server.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|e| invalid(format!("Invalid SMTP port {:?}: {}", port, e)))?;
+                (host, Some(port))
+            }
+            None => (server, None),
+        };
+        let from = from
+            .parse()
+            .map_err(|e| invalid(format!("Invalid sender {:?}: {}", from, e)))?;
+
+        let mut builder = match security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(io::Error::other)?;
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpTransport {
+            transport: builder.build(),
+            from,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Time after which a delivery is given up, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> SmtpTransport {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn deliver(&self, to: &str, subject: &str, body: &str) -> io::Result<()> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .message_id(Some(message_id(self.from.email.as_ref())))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_owned())
+            .map_err(io::Error::other)?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    fn send<'a>(
+        &'a self,
+        to: &'a str,
+        subject: &'a str,
+        body: &'a str,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            match tokio::time::timeout(self.timeout, self.deliver(to, subject, body)).await {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "SMTP delivery timed out",
+                )),
+            }
+        })
+    }
+}
+
+/// Unique id in the domain of the sender
+fn message_id(from: &str) -> String {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).expect("No random source available");
+    let domain = from.rsplit_once('@').map_or("localhost", |(_, d)| d);
+    format!(
+        "<{}.{:016x}@{}>",
+        Utc::now().timestamp(),
+        u64::from_le_bytes(bytes),
+        domain
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn transport(server: &str) -> SmtpTransport {
+        SmtpTransport::new(server, "bot@example.org", SmtpSecurity::None, None).unwrap()
+    }
+
+    #[test]
+    fn valid_emails_are_accepted() {
+        for email in [
+            "max.mustermann@uni-ulm.de",
+            "a@b.de",
+            "first+tag@mail.example.org",
+            "o'brien@example.com",
+        ] {
+            assert!(is_valid_email(email), "{}", email);
+        }
+    }
+
+    #[test]
+    fn invalid_emails_are_rejected() {
+        for email in [
+            "",
+            "max.mustermann",
+            "max@uni-ulm",
+            "@uni-ulm.de",
+            "max@",
+            "max@@uni-ulm.de",
+            "max@uni..de",
+            "max@.uni-ulm.de",
+            "max@uni-ulm.d",
+            "max@-uni.de",
+            ".max@uni-ulm.de",
+            "max..m@uni-ulm.de",
+            "max m@uni-ulm.de",
+            "max@uni-ulm.de ",
+            "Max <max@uni-ulm.de>",
+            "max@uni-ulm.de,moritz@uni-ulm.de",
+        ] {
+            assert!(!is_valid_email(email), "{}", email);
+        }
+    }
+
+    #[test]
+    fn codes_have_six_digits() {
+        for _ in 0..100 {
+            let code = verification_code();
+            assert_eq!(code.len(), 6);
+            assert!(code.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    /// Accepts one mail and returns the received lines.
+    async fn sink(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut lines = vec![];
+        let mut in_data = false;
+
+        writer.write_all(b"220 sink\r\n").await.unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end_matches("\r\n").to_owned();
+            let reply: &[u8] = if in_data {
+                in_data = line != ".";
+                if in_data {
+                    b""
+                } else {
+                    b"250 queued\r\n"
+                }
+            } else if line.starts_with("EHLO") {
+                b"250-sink\r\n250 8BITMIME\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                lines.push(line);
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn mail_is_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let received = tokio::spawn(sink(listener));
+
+        transport(&server)
+            .send(
+                "max@uni-ulm.de",
+                "Dein Bestätigungscode",
+                "Hallo\n.\nCode: 123456",
+            )
+            .await
+            .unwrap();
+
+        let lines = received.await.unwrap();
+        assert!(lines[0].starts_with("EHLO "), "{}", lines[0]);
+        assert!(
+            lines[1].starts_with("MAIL FROM:<bot@example.org>"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].starts_with("RCPT TO:<max@uni-ulm.de>"),
+            "{}",
+            lines[2]
+        );
+        assert_eq!(lines[3], "DATA");
+        // Encoded, as it is not ASCII
+        assert!(lines.contains(&"Subject: Dein =?utf-8?b?QmVzdMOkdGlndW5nc2NvZGU=?=".to_owned()));
+        assert!(lines.iter().any(|l| l.starts_with("Date: ")));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("Message-ID: <") && l.ends_with("@example.org>")));
+        // The body is separated by an empty line and dot-stuffed
+        let body = lines.iter().position(String::is_empty).unwrap() + 1;
+        assert_eq!(lines[body..], ["Hallo", "..", "Code: 123456", ".", "QUIT"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_recipient_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 sink\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = if line.starts_with("RCPT") {
+                    b"550 no such user\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+
+        let result = transport(&server)
+            .send("nobody@uni-ulm.de", "Code", "123456")
+            .await;
+
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("no such user"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stalled_server_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        // Accepts, but never greets
+        tokio::spawn(async move {
+            let _connection = listener.accept().await;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let result = transport(&server)
+            .with_timeout(Duration::from_millis(100))
+            .send("max@uni-ulm.de", "Code", "123456")
+            .await;
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn security_is_parsed() {
+        assert_eq!("STARTTLS".parse(), Ok(SmtpSecurity::StartTls));
+        assert_eq!("tls".parse(), Ok(SmtpSecurity::Tls));
+        assert_eq!("none".parse(), Ok(SmtpSecurity::None));
+        assert!("ssl".parse::<SmtpSecurity>().is_err());
+    }
+
+    /// Counts the mails instead of sending them
+    #[derive(Default)]
+    struct CountingTransport(Arc<Mutex<usize>>);
+
+    impl MailTransport for CountingTransport {
+        fn send<'a>(&'a self, _: &'a str, _: &'a str, _: &'a str) -> BoxFuture<'a, io::Result<()>> {
+            *self.0.lock().unwrap() += 1;
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn mails_of_a_chat_are_limited() {
+        let sent = Arc::default();
+        let mailer = VerificationMailer::new(CountingTransport(Arc::clone(&sent)));
+        let chat = ChatId(1);
+
+        mailer
+            .send(chat, "a@example.org", "Code", "1")
+            .await
+            .unwrap();
+        let second = mailer.send(chat, "b@example.org", "Code", "2").await;
+        assert!(
+            matches!(second, Err(SendError::Limited { retry_after }) if retry_after <= MAIL_COOLDOWN)
+        );
+        // Other chats are not affected
+        mailer
+            .send(ChatId(2), "c@example.org", "Code", "3")
+            .await
+            .unwrap();
+        assert_eq!(*sent.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn mails_per_day_are_limited() {
+        let mailer = VerificationMailer::new(CountingTransport::default());
+        let chat = ChatId(1);
+        let start = Instant::now();
+        let at = |minutes| start + Duration::from_secs(minutes * 60);
+
+        // One mail every two minutes
+        let limit = MAX_MAILS_PER_WINDOW as u64;
+        for i in 0..limit {
+            assert_eq!(mailer.reserve(chat, at(2 * i)), Ok(()));
+        }
+        let retry_after = mailer.reserve(chat, at(2 * limit)).unwrap_err();
+        assert_eq!(
+            retry_after,
+            MAIL_LIMIT_WINDOW - Duration::from_secs(2 * limit * 60)
+        );
+        // The first mail is a day old, the others still count
+        assert_eq!(mailer.reserve(chat, at(24 * 60)), Ok(()));
+        assert!(mailer.reserve(chat, at(24 * 60 + 1)).is_err());
+    }
+}
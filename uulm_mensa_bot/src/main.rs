@@ -1,21 +1,52 @@
+mod alerts;
+mod i18n;
+mod mail;
+mod subscriptions;
+mod waitlist;
+
+use alerts::{parse_alert, Alerts, ALERT_CALLBACK_PREFIX};
 use chrono::prelude::*;
+use i18n::{telegram_language, Texts};
 use log::warn;
-use my_mensa_lib::{DayMenu, LinkedHashMap, UserProfile};
+use mail::{
+    is_valid_email, verification_code, Mailer, SendError, SmtpSecurity, SmtpTransport,
+    VerificationMailer,
+};
+use my_mensa_lib::{
+    format_price, DayMenu, Diet, Language, Mensa, MensaClient, PriceGroup, TimeSlot, UserProfile,
+    DEFAULT_MENSA_ID,
+};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool};
 use std::sync::atomic::Ordering::Relaxed;
-use std::{future::IntoFuture, sync::atomic::AtomicBool};
+use std::{
+    future::IntoFuture,
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc,
+    },
+    time::Duration,
+};
+use subscriptions::{parse_schedule, Subscription, Subscriptions};
 use teloxide::{
     dispatching::{
         dialogue::{self, serializer::Json, ErasedStorage, InMemStorage, SqliteStorage, Storage},
         UpdateHandler,
     },
     prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId},
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageId, User},
     utils::command::BotCommands,
 };
 use tokio::join;
+use waitlist::{parse_freed_slot, Waitlist, WaitlistEntry, FREED_SLOT_CALLBACK_PREFIX};
 
 static STAGING: AtomicBool = AtomicBool::new(true);
 
+/// Minutes after which an unfinished order dialogue is cancelled, 0 to keep it forever
+static ORDER_TIMEOUT_MINUTES: AtomicU64 = AtomicU64::new(10);
+
+/// Wrong verification codes accepted before the email address has to be entered again
+const MAX_VERIFICATION_ATTEMPTS: u32 = 5;
+
 type MyDialogue = Dialogue<State, ErasedStorage<State>>;
 type MyStorage = std::sync::Arc<ErasedStorage<State>>;
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
@@ -36,9 +67,67 @@ async fn main() {
         }
     };
 
+    if let Ok(minutes) = std::env::var("ORDER_TIMEOUT_MINUTES") {
+        match minutes.parse() {
+            Ok(minutes) => ORDER_TIMEOUT_MINUTES.store(minutes, Relaxed),
+            Err(e) => log::warn!("Invalid ORDER_TIMEOUT_MINUTES {:?}: {}", minutes, e),
+        }
+    }
+
+    let mut client = MensaClient::builder();
+    if let Ok(url) = std::env::var("MENSA_API_BASE_URL") {
+        log::info!("Using my-mensa API at {}", url);
+        client = client.api_base_url(url);
+    }
+    if let Ok(url) = std::env::var("MENSA_TOGO_API_URL") {
+        log::info!("Using my-mensa to-go API at {}", url);
+        client = client.togo_api_url(url);
+    }
+    if let Ok(path) = std::env::var("MENU_CACHE_FILE") {
+        client = client.menu_cache_file(path);
+    }
+    let client = client.build().expect("Failed to build my-mensa client");
+
+    let mailer: Mailer = match (std::env::var("SMTP_SERVER"), std::env::var("SMTP_FROM")) {
+        (Ok(server), Ok(from)) => {
+            let security = match std::env::var("SMTP_SECURITY") {
+                Ok(security) => security.parse().expect("Invalid SMTP_SECURITY"),
+                Err(_) => SmtpSecurity::StartTls,
+            };
+            let credentials = std::env::var("SMTP_USERNAME")
+                .ok()
+                .map(|username| (username, std::env::var("SMTP_PASSWORD").unwrap_or_default()));
+            log::info!(
+                "Verifying email addresses, sending mails via {} ({:?})",
+                server,
+                security
+            );
+            let mut transport = SmtpTransport::new(&server, &from, security, credentials)
+                .expect("Invalid SMTP configuration");
+            if let Ok(seconds) = std::env::var("SMTP_TIMEOUT_SECONDS") {
+                match seconds.parse() {
+                    Ok(seconds) => transport = transport.with_timeout(Duration::from_secs(seconds)),
+                    Err(e) => log::warn!("Invalid SMTP_TIMEOUT_SECONDS {:?}: {}", seconds, e),
+                }
+            }
+            Some(Arc::new(VerificationMailer::new(transport)))
+        }
+        _ => None,
+    };
+
     let bot = Bot::from_env();
 
-    let storage: MyStorage = if std::env::var("PERSISTENCE_SQLITE").is_ok() {
+    // English for all clients without a more specific list
+    for (language, code) in [(Language::English, None), (Language::German, Some("de"))] {
+        let mut request = bot.set_my_commands(Texts::new(language).bot_commands());
+        request.language_code = code.map(str::to_owned);
+        if let Err(e) = request.await {
+            log::warn!("Failed to register {} commands: {}", language, e);
+        }
+    }
+
+    let sqlite = std::env::var("PERSISTENCE_SQLITE").is_ok();
+    let storage: MyStorage = if sqlite {
         SqliteStorage::open("db.sqlite", Json)
             .await
             .unwrap()
@@ -47,28 +136,107 @@ async fn main() {
         InMemStorage::new().erase()
     };
 
+    // Shares db.sqlite with the dialogue storage, which has its own pool. WAL mode lets reads
+    // run next to a write, and writers wait for each other instead of failing as locked.
+    let db = if sqlite {
+        let options = SqliteConnectOptions::new()
+            .filename("db.sqlite")
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(10));
+        Some(SqlitePool::connect_with(options).await.unwrap())
+    } else {
+        None
+    };
+    let waitlist = Waitlist::open(bot.clone(), client.clone(), storage.clone(), db.clone())
+        .await
+        .expect("Failed to open waitlist");
+    let subscriptions =
+        Subscriptions::open(bot.clone(), client.clone(), storage.clone(), db.clone())
+            .await
+            .expect("Failed to open subscriptions");
+    let alerts = Alerts::open(bot.clone(), client.clone(), storage.clone(), db)
+        .await
+        .expect("Failed to open alerts");
+
     Dispatcher::builder(bot, schema())
-        .dependencies(dptree::deps![storage])
+        .dependencies(dptree::deps![
+            storage,
+            client,
+            mailer,
+            waitlist,
+            subscriptions,
+            alerts
+        ])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
         .await;
 }
 
+/// Usage and descriptions are in [`Texts::help`]
 #[derive(BotCommands, Clone)]
-#[command(
-    rename_rule = "lowercase",
-    description = "These commands are supported:"
-)]
+#[command(rename_rule = "lowercase")]
 enum Command {
-    #[command(description = "Display this help text")]
     Help,
-    #[command(description = "Restart the welcome dialog")]
     Start,
-    #[command(description = "Show menu for the next days")]
-    Menu,
-    #[command(description = "/order [date]: Display order form")]
+    Menu(String),
     Order,
+    Mensa,
+    Language(String),
+    Subscribe(String),
+    Unsubscribe,
+    Watch(String),
+    Unwatch(String),
+    Cancel,
+    Profile,
+    MyData,
+    DeleteMe,
+}
+
+/// Registered user: contact data sent with orders, and settings
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct Profile {
+    #[serde(flatten)]
+    contact: UserProfile,
+    /// Mensa used for menus and orders
+    #[serde(default = "default_mensa_id")]
+    mensa_id: i32,
+    /// Language of the bot and the menu
+    #[serde(default)]
+    language: Language,
+    /// Prices shown in menus and order summaries
+    #[serde(default)]
+    price_group: PriceGroup,
+}
+
+/// Contact field edited with `/profile`
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+enum ProfileField {
+    FirstName,
+    LastName,
+    Email,
+}
+
+impl ProfileField {
+    const ALL: [ProfileField; 3] = [
+        ProfileField::FirstName,
+        ProfileField::LastName,
+        ProfileField::Email,
+    ];
+
+    /// Name used in callback data
+    fn key(self) -> &'static str {
+        match self {
+            ProfileField::FirstName => "firstname",
+            ProfileField::LastName => "lastname",
+            ProfileField::Email => "email",
+        }
+    }
+}
+
+fn default_mensa_id() -> i32 {
+    DEFAULT_MENSA_ID
 }
 
 #[derive(Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
@@ -82,251 +250,1435 @@ enum State {
         first_name: String,
         last_name: String,
     },
+    VerifyingEmail {
+        first_name: String,
+        last_name: String,
+        email: String,
+        /// Code sent to `email`
+        code: String,
+        /// Wrong codes entered so far
+        attempts: u32,
+    },
     Idle {
-        user: UserProfile,
+        user: Profile,
     },
     WaitingForOrderSelection {
-        user: UserProfile,
-        iso_date: String,
+        user: Profile,
+        #[serde(alias = "iso_date")]
+        date: NaiveDate,
         order_select_message: MessageId,
+        /// When the order is cancelled if nothing was selected, `None` to keep it open
+        #[serde(default)]
+        expires_at: Option<DateTime<Utc>>,
     },
     WaitingForSlotSelection {
-        user: UserProfile,
-        iso_date: String,
+        user: Profile,
+        #[serde(alias = "iso_date")]
+        date: NaiveDate,
         order_md5: String,
         slot_select_message: MessageId,
+        /// Mensa of the order, `None` for the mensa of the profile
+        #[serde(default)]
+        mensa_id: Option<i32>,
+        #[serde(default)]
+        expires_at: Option<DateTime<Utc>>,
     },
+    WaitingForConfirmation {
+        user: Profile,
+        date: NaiveDate,
+        order_md5: String,
+        #[serde(default)]
+        mensa_id: Option<i32>,
+        /// Start of the selected slot
+        slot: NaiveTime,
+        confirm_message: MessageId,
+        #[serde(default)]
+        expires_at: Option<DateTime<Utc>>,
+    },
+    EditingProfile {
+        user: Profile,
+        field: ProfileField,
+    },
+    /// Waiting for the code mailed to the new address entered with `/profile`
+    VerifyingNewEmail {
+        user: Profile,
+        email: String,
+        code: String,
+        attempts: u32,
+    },
+}
+
+impl State {
+    /// Profile of the registered user, `None` during setup
+    fn profile(&self) -> Option<&Profile> {
+        match self {
+            State::Idle { user }
+            | State::WaitingForOrderSelection { user, .. }
+            | State::WaitingForSlotSelection { user, .. }
+            | State::WaitingForConfirmation { user, .. }
+            | State::EditingProfile { user, .. }
+            | State::VerifyingNewEmail { user, .. } => Some(user),
+            _ => None,
+        }
+    }
+
+    /// Profile, or the details entered so far during setup. Verification codes are left out,
+    /// they must only be known to the owner of the mailbox.
+    fn personal_data(&self) -> serde_json::Value {
+        match self {
+            State::WaitingForFirstName => serde_json::json!({}),
+            State::WaitingForLastName { first_name } => {
+                serde_json::json!({ "first_name": first_name })
+            }
+            State::ReceiveEmail {
+                first_name,
+                last_name,
+            } => serde_json::json!({ "first_name": first_name, "last_name": last_name }),
+            State::VerifyingEmail {
+                first_name,
+                last_name,
+                email,
+                ..
+            } => serde_json::json!({
+                "first_name": first_name,
+                "last_name": last_name,
+                "unverified_email": email,
+            }),
+            State::VerifyingNewEmail { user, email, .. } => {
+                let mut data = serde_json::json!(user);
+                data["unverified_email"] = serde_json::json!(email);
+                data
+            }
+            state => serde_json::json!(state.profile()),
+        }
+    }
+
+    /// Message with the keyboard of an unfinished order
+    fn keyboard_message(&self) -> Option<MessageId> {
+        match self {
+            State::WaitingForOrderSelection {
+                order_select_message,
+                ..
+            } => Some(*order_select_message),
+            State::WaitingForSlotSelection {
+                slot_select_message,
+                ..
+            } => Some(*slot_select_message),
+            State::WaitingForConfirmation {
+                confirm_message, ..
+            } => Some(*confirm_message),
+            _ => None,
+        }
+    }
+
+    /// Whether the unfinished order outlived its deadline, e.g. while the bot was not running
+    fn is_expired(&self) -> bool {
+        let expires_at = match self {
+            State::WaitingForOrderSelection { expires_at, .. }
+            | State::WaitingForSlotSelection { expires_at, .. }
+            | State::WaitingForConfirmation { expires_at, .. } => *expires_at,
+            _ => None,
+        };
+        expires_at.is_some_and(|t| t <= Utc::now())
+    }
+
+    /// Language of the registered user, or of the Telegram client during setup
+    fn language(&self, from: Option<&User>) -> Language {
+        match self.profile() {
+            Some(profile) => profile.language,
+            None => telegram_language(from),
+        }
+    }
+}
+
+/// Prefix of the callback data of the mensa selection buttons
+const MENSA_CALLBACK_PREFIX: &str = "mensa:";
+
+/// Prefix of the callback data of the `/profile` buttons, followed by the field key or
+/// [`PRICE_GROUP_KEY`]
+const PROFILE_CALLBACK_PREFIX: &str = "profile:";
+const PRICE_GROUP_KEY: &str = "price";
+
+/// Prefix of the callback data of the price group buttons
+const PRICE_GROUP_CALLBACK_PREFIX: &str = "price:";
+
+fn make_profile_buttons(texts: Texts) -> InlineKeyboardMarkup {
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = ProfileField::ALL
+        .iter()
+        .map(|field| {
+            vec![InlineKeyboardButton::callback(
+                texts.edit_field_button(*field),
+                format!("{}{}", PROFILE_CALLBACK_PREFIX, field.key()),
+            )]
+        })
+        .collect();
+    keyboard.push(vec![InlineKeyboardButton::callback(
+        texts.edit_price_group_button(),
+        format!("{}{}", PROFILE_CALLBACK_PREFIX, PRICE_GROUP_KEY),
+    )]);
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+fn make_price_group_buttons(texts: Texts) -> InlineKeyboardMarkup {
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = PriceGroup::ALL
+        .iter()
+        .map(|group| {
+            vec![InlineKeyboardButton::callback(
+                texts.price_group(*group),
+                format!("{}{}", PRICE_GROUP_CALLBACK_PREFIX, group.name_en()),
+            )]
+        })
+        .collect();
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Prefix of the callback data of the waitlist buttons, followed by the window for automatic
+/// orders, e.g. `12:00-12:30`, or nothing to only get notified
+const WAITLIST_CALLBACK_PREFIX: &str = "waitlist:";
+
+fn make_mensa_buttons(mensas: &[Mensa], texts: Texts) -> InlineKeyboardMarkup {
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = mensas
+        .iter()
+        .map(|mensa| {
+            let label = if mensa.togo {
+                mensa.name.clone()
+            } else {
+                texts.mensa_without_ordering(&mensa.name)
+            };
+            vec![InlineKeyboardButton::callback(
+                label,
+                format!("{}{}", MENSA_CALLBACK_PREFIX, mensa.id),
+            )]
+        })
+        .collect();
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+fn make_timeslot_buttons(slots: &[TimeSlot], texts: Texts) -> InlineKeyboardMarkup {
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = slots
+        .iter()
+        .filter(|slot| slot.is_free())
+        .map(|slot| {
+            vec![InlineKeyboardButton::callback(
+                texts.slot_button(slot),
+                slot.start.format("%H:%M").to_string(),
+            )]
+        })
+        .chain([cancel_button(texts)])
+        .collect();
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Callback data of the buttons of the order summary
+const CONFIRM_CALLBACK: &str = "confirm";
+const CHANGE_SLOT_CALLBACK: &str = "change_slot";
+/// Callback data of the cancel button of all order keyboards
+const CANCEL_CALLBACK: &str = "cancel";
+
+/// Callback data of the buttons asking whether to delete all data of the chat
+const DELETE_DATA_CALLBACK: &str = "delete_data";
+const KEEP_DATA_CALLBACK: &str = "keep_data";
+
+fn make_delete_data_buttons(texts: Texts) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(texts.delete_data_button(), DELETE_DATA_CALLBACK),
+        InlineKeyboardButton::callback(texts.keep_data_button(), KEEP_DATA_CALLBACK),
+    ]])
+}
+
+fn cancel_button(texts: Texts) -> Vec<InlineKeyboardButton> {
+    vec![InlineKeyboardButton::callback(
+        texts.cancel_button(),
+        CANCEL_CALLBACK,
+    )]
+}
+
+fn make_confirm_buttons(texts: Texts) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback(
+            texts.confirm_button(),
+            CONFIRM_CALLBACK,
+        )],
+        vec![
+            InlineKeyboardButton::callback(texts.change_slot_button(), CHANGE_SLOT_CALLBACK),
+            InlineKeyboardButton::callback(texts.cancel_button(), CANCEL_CALLBACK),
+        ],
+    ])
+}
+
+/// Offers to wait for any slot, or to order automatically in one of the half-hour windows
+fn make_waitlist_buttons(slots: &[TimeSlot], texts: Texts) -> InlineKeyboardMarkup {
+    let mut keyboard = vec![vec![InlineKeyboardButton::callback(
+        texts.waitlist_notify_button(),
+        WAITLIST_CALLBACK_PREFIX,
+    )]];
+    for window in slots.chunks(2) {
+        let from = window[0].start.format("%H:%M");
+        let to = window[window.len() - 1].end.format("%H:%M");
+        keyboard.push(vec![InlineKeyboardButton::callback(
+            texts.waitlist_auto_button(&from.to_string(), &to.to_string()),
+            format!("{}{}-{}", WAITLIST_CALLBACK_PREFIX, from, to),
+        )]);
+    }
+    keyboard.push(cancel_button(texts));
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+fn make_menu_buttons(menu: &DayMenu, texts: Texts) -> InlineKeyboardMarkup {
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
+
+    for meal in menu.meals.iter() {
+        let row = vec![InlineKeyboardButton::callback(
+            meal.combined_name.clone(),
+            meal.md5.clone(),
+        )];
+
+        keyboard.push(row);
+    }
+    keyboard.push(cancel_button(texts));
+
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Profile of a registered user, for tasks outside of a dialogue
+async fn stored_profile(storage: &MyStorage, chat_id: ChatId) -> Option<Profile> {
+    let state = storage.clone().get_dialogue(chat_id).await.ok()??;
+    state.profile().cloned()
+}
+
+/// Texts in the language of the user
+async fn texts(dialogue: &MyDialogue, from: Option<&User>) -> Texts {
+    let state = dialogue.get().await.ok().flatten().unwrap_or_default();
+    Texts::new(state.language(from))
+}
+
+async fn help(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let texts = texts(&dialogue, msg.from()).await;
+    bot.send_message(msg.chat.id, texts.help()).await?;
+    Ok(())
+}
+
+async fn start(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let texts = texts(&dialogue, msg.from()).await;
+    bot.send_message(msg.chat.id, texts.ask_first_name())
+        .await?;
+
+    dialogue.update(State::WaitingForFirstName).await?;
+    Ok(())
+}
+
+async fn receive_first_name(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let texts = Texts::new(telegram_language(msg.from()));
+    bot.send_message(msg.chat.id, texts.ask_last_name(msg.text().unwrap()))
+        .await?;
+    dialogue
+        .update(State::WaitingForLastName {
+            first_name: msg.text().unwrap().to_owned(),
+        })
+        .await?;
+    Ok(())
+}
+
+async fn receive_last_name(
+    bot: Bot,
+    dialogue: MyDialogue,
+    first_name: String,
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(telegram_language(msg.from()));
+    let last_name = msg.text().unwrap();
+    bot.send_message(msg.chat.id, texts.ask_email(&first_name, last_name))
+        .await?;
+    dialogue
+        .update(State::ReceiveEmail {
+            first_name,
+            last_name: last_name.to_owned(),
+        })
+        .await?;
+    Ok(())
+}
+
+async fn receive_email(
+    bot: Bot,
+    client: MensaClient,
+    mailer: Mailer,
+    dialogue: MyDialogue,
+    (first_name, last_name): (String, String),
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(telegram_language(msg.from()));
+    let email = msg.text().unwrap_or_default().trim();
+    if !is_valid_email(email) {
+        bot.send_message(msg.chat.id, texts.invalid_email(email))
+            .await?;
+        return Ok(());
+    }
+
+    let mailer = match mailer {
+        Some(mailer) => mailer,
+        None => {
+            return complete_setup(&bot, &client, &dialogue, &msg, first_name, last_name, email)
+                .await
+        }
+    };
+    let sent = send_verification_code(&bot, &mailer, texts, msg.chat.id, &first_name, email);
+    if let Some(code) = sent.await? {
+        dialogue
+            .update(State::VerifyingEmail {
+                first_name,
+                last_name,
+                email: email.to_owned(),
+                code,
+                attempts: 0,
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Mails a new code to `email`. Returns `None` if the mail could not be sent or the chat
+/// requested too many mails, the user is then asked to enter the address again.
+async fn send_verification_code(
+    bot: &Bot,
+    mailer: &VerificationMailer,
+    texts: Texts,
+    chat_id: ChatId,
+    first_name: &str,
+    email: &str,
+) -> Result<Option<String>, teloxide::RequestError> {
+    let code = verification_code();
+
+    let body = texts.verification_mail_body(first_name, &code);
+    match mailer
+        .send(chat_id, email, texts.verification_mail_subject(), &body)
+        .await
+    {
+        Ok(()) => {}
+        Err(SendError::Limited { retry_after }) => {
+            log::info!("Too many verification mails for chat {}", chat_id);
+            bot.send_message(chat_id, texts.mail_limited(retry_after))
+                .await?;
+            return Ok(None);
+        }
+        Err(SendError::Failed(e)) => {
+            log::warn!("Failed to send verification mail: {}", e);
+            bot.send_message(chat_id, texts.mail_failed()).await?;
+            return Ok(None);
+        }
+    }
+
+    bot.send_message(chat_id, texts.verification_sent(email))
+        .await?;
+    Ok(Some(code))
+}
+
+/// Reply to a mailed verification code
+enum VerificationAnswer {
+    Correct,
+    /// A corrected address, to send a new code to
+    NewAddress,
+    Wrong,
+    /// Wrong, and the address has to be entered again
+    Exhausted,
+}
+
+fn verification_answer(
+    text: &str,
+    code: &str,
+    attempts: u32,
+    can_resend: bool,
+) -> VerificationAnswer {
+    if text == code {
+        VerificationAnswer::Correct
+    } else if can_resend && is_valid_email(text) {
+        VerificationAnswer::NewAddress
+    } else if attempts + 1 >= MAX_VERIFICATION_ATTEMPTS {
+        VerificationAnswer::Exhausted
+    } else {
+        VerificationAnswer::Wrong
+    }
+}
+
+async fn receive_verification_code(
+    bot: Bot,
+    client: MensaClient,
+    mailer: Mailer,
+    dialogue: MyDialogue,
+    (first_name, last_name, email, code, attempts): (String, String, String, String, u32),
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(telegram_language(msg.from()));
+    let text = msg.text().unwrap_or_default().trim();
+
+    let next = match verification_answer(text, &code, attempts, mailer.is_some()) {
+        VerificationAnswer::Correct => {
+            return complete_setup(
+                &bot, &client, &dialogue, &msg, first_name, last_name, &email,
+            )
+            .await;
+        }
+        VerificationAnswer::NewAddress => {
+            let mailer = mailer.as_deref().unwrap();
+            match send_verification_code(&bot, mailer, texts, msg.chat.id, &first_name, text)
+                .await?
+            {
+                Some(code) => State::VerifyingEmail {
+                    first_name,
+                    last_name,
+                    email: text.to_owned(),
+                    code,
+                    attempts: 0,
+                },
+                None => State::ReceiveEmail {
+                    first_name,
+                    last_name,
+                },
+            }
+        }
+        VerificationAnswer::Exhausted => {
+            bot.send_message(msg.chat.id, texts.too_many_attempts())
+                .await?;
+            State::ReceiveEmail {
+                first_name,
+                last_name,
+            }
+        }
+        VerificationAnswer::Wrong => {
+            bot.send_message(msg.chat.id, texts.wrong_code()).await?;
+            State::VerifyingEmail {
+                first_name,
+                last_name,
+                email,
+                code,
+                attempts: attempts + 1,
+            }
+        }
+    };
+    dialogue.update(next).await?;
+    Ok(())
+}
+
+/// Code sent to the new address of a registered user, the profile keeps the old address
+/// until it is entered.
+async fn receive_new_email_code(
+    bot: Bot,
+    mailer: Mailer,
+    dialogue: MyDialogue,
+    (mut user, email, code, attempts): (Profile, String, String, u32),
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let text = msg.text().unwrap_or_default().trim();
+    let retry_email = |user| State::EditingProfile {
+        user,
+        field: ProfileField::Email,
+    };
+
+    let next = match verification_answer(text, &code, attempts, mailer.is_some()) {
+        VerificationAnswer::Correct => {
+            user.contact.email = email;
+            bot.send_message(
+                msg.chat.id,
+                texts.profile_saved(&user.contact, user.price_group),
+            )
+            .await?;
+            State::Idle { user }
+        }
+        VerificationAnswer::NewAddress => {
+            let mailer = mailer.as_deref().unwrap();
+            let first_name = &user.contact.firstname;
+            match send_verification_code(&bot, mailer, texts, msg.chat.id, first_name, text).await?
+            {
+                Some(code) => State::VerifyingNewEmail {
+                    user,
+                    email: text.to_owned(),
+                    code,
+                    attempts: 0,
+                },
+                None => retry_email(user),
+            }
+        }
+        VerificationAnswer::Exhausted => {
+            bot.send_message(msg.chat.id, texts.too_many_attempts())
+                .await?;
+            retry_email(user)
+        }
+        VerificationAnswer::Wrong => {
+            bot.send_message(msg.chat.id, texts.wrong_code()).await?;
+            State::VerifyingNewEmail {
+                user,
+                email,
+                code,
+                attempts: attempts + 1,
+            }
+        }
+    };
+    dialogue.update(next).await?;
+    Ok(())
+}
+
+async fn complete_setup(
+    bot: &Bot,
+    client: &MensaClient,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    first_name: String,
+    last_name: String,
+    email: &str,
+) -> HandlerResult {
+    let language = telegram_language(msg.from());
+    let texts = Texts::new(language);
+    bot.send_message(
+        msg.chat.id,
+        texts.setup_complete(&first_name, &last_name, email),
+    )
+    .await?;
+
+    dialogue
+        .update(State::Idle {
+            user: Profile {
+                contact: UserProfile::new(first_name, last_name, email.to_owned()),
+                mensa_id: DEFAULT_MENSA_ID,
+                language,
+                price_group: PriceGroup::default(),
+            },
+        })
+        .await?;
+
+    // The profile is complete, the mensa can also be chosen later
+    if let Err(e) = present_mensas(bot, client, msg.chat.id, texts).await {
+        warn!("Failed to list mensas: {}", e);
+        bot.send_message(msg.chat.id, texts.mensas_unavailable())
+            .await?;
+    }
+    Ok(())
+}
+
+async fn present_mensas(
+    bot: &Bot,
+    client: &MensaClient,
+    chat_id: ChatId,
+    texts: Texts,
+) -> HandlerResult {
+    let mensas = client.list_mensas().await?;
+    bot.send_message(chat_id, texts.ask_mensa())
+        .reply_markup(make_mensa_buttons(&mensas, texts))
+        .await?;
+    Ok(())
+}
+
+async fn choose_mensa(bot: Bot, client: MensaClient, user: Profile, msg: Message) -> HandlerResult {
+    present_mensas(&bot, &client, msg.chat.id, Texts::new(user.language)).await
+}
+
+async fn set_language(
+    bot: Bot,
+    dialogue: MyDialogue,
+    mut user: Profile,
+    msg: Message,
+    language: String,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let text = match language.trim() {
+        "" => texts.language_current().to_owned(),
+        l => match l.parse::<Language>() {
+            Ok(language) => {
+                user.language = language;
+                dialogue.update(State::Idle { user }).await?;
+                Texts::new(language).language_selected().to_owned()
+            }
+            Err(_) => texts.unknown_language(l),
+        },
+    };
+
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn subscribe(
+    bot: Bot,
+    subscriptions: Arc<Subscriptions>,
+    user: Profile,
+    msg: Message,
+    args: String,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let (time, weekdays) = match parse_schedule(&args) {
+        Some(schedule) => schedule,
+        None => {
+            bot.send_message(msg.chat.id, texts.invalid_schedule())
+                .await?;
+            return Ok(());
+        }
+    };
+
+    subscriptions
+        .subscribe(Subscription {
+            chat_id: msg.chat.id,
+            time,
+            weekdays: weekdays.clone(),
+        })
+        .await?;
+
+    bot.send_message(msg.chat.id, texts.subscribed(time, &weekdays))
+        .await?;
+    Ok(())
+}
+
+async fn unsubscribe(
+    bot: Bot,
+    subscriptions: Arc<Subscriptions>,
+    user: Profile,
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let text = if subscriptions.unsubscribe(msg.chat.id).await? {
+        texts.unsubscribed()
+    } else {
+        texts.not_subscribed()
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn watch(
+    bot: Bot,
+    alerts: Arc<Alerts>,
+    user: Profile,
+    msg: Message,
+    keyword: String,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let text = match keyword.trim() {
+        "" => texts.keywords(&alerts.keywords(msg.chat.id)),
+        keyword => {
+            let menu = (user.mensa_id, user.language);
+            if alerts.watch(msg.chat.id, keyword, menu).await? {
+                texts.watching(keyword)
+            } else {
+                texts.already_watching(keyword)
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn unwatch(
+    bot: Bot,
+    alerts: Arc<Alerts>,
+    user: Profile,
+    msg: Message,
+    keyword: String,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let keyword = keyword.trim();
+    let text = if keyword.is_empty() {
+        texts.keywords(&alerts.keywords(msg.chat.id))
+    } else if alerts.unwatch(msg.chat.id, keyword).await? {
+        texts.not_watching_anymore(keyword)
+    } else {
+        texts.not_watching(keyword)
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn show_profile(bot: Bot, user: Profile, msg: Message) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    bot.send_message(msg.chat.id, texts.profile(&user.contact, user.price_group))
+        .reply_markup(make_profile_buttons(texts))
+        .await?;
+    Ok(())
+}
+
+/// Edit button of `/profile`
+async fn profile_callback(
+    bot: Bot,
+    dialogue: MyDialogue,
+    user: Profile,
+    q: CallbackQuery,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let key = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(PROFILE_CALLBACK_PREFIX));
+
+    if let Some(message) = q.message {
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
+    }
+
+    if key == Some(PRICE_GROUP_KEY) {
+        bot.send_message(dialogue.chat_id(), texts.ask_price_group())
+            .reply_markup(make_price_group_buttons(texts))
+            .await?;
+        return Ok(());
+    }
+
+    let field = match ProfileField::ALL.into_iter().find(|f| Some(f.key()) == key) {
+        Some(field) => field,
+        None => return Ok(()),
+    };
+    bot.send_message(dialogue.chat_id(), texts.ask_field(field))
+        .await?;
+    dialogue
+        .update(State::EditingProfile { user, field })
+        .await?;
+    Ok(())
+}
+
+async fn receive_profile_field(
+    bot: Bot,
+    mailer: Mailer,
+    dialogue: MyDialogue,
+    (mut user, field): (Profile, ProfileField),
+    msg: Message,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let value = match msg.text().map(str::trim) {
+        Some(value) if !value.is_empty() => value.to_owned(),
+        _ => {
+            bot.send_message(msg.chat.id, texts.ask_field(field))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if let ProfileField::Email = field {
+        if !is_valid_email(&value) {
+            bot.send_message(msg.chat.id, texts.invalid_email(&value))
+                .await?;
+            return Ok(());
+        }
+        // The new address has to be verified like during the setup
+        if let Some(mailer) = mailer {
+            let first_name = &user.contact.firstname;
+            let sent =
+                send_verification_code(&bot, &mailer, texts, msg.chat.id, first_name, &value);
+            if let Some(code) = sent.await? {
+                dialogue
+                    .update(State::VerifyingNewEmail {
+                        user,
+                        email: value,
+                        code,
+                        attempts: 0,
+                    })
+                    .await?;
+            }
+            return Ok(());
+        }
+    }
+
+    match field {
+        ProfileField::FirstName => user.contact.firstname = value,
+        ProfileField::LastName => user.contact.lastname = value,
+        ProfileField::Email => user.contact.email = value,
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        texts.profile_saved(&user.contact, user.price_group),
+    )
+    .await?;
+    dialogue.update(State::Idle { user }).await?;
+    Ok(())
+}
+
+async fn price_group_callback(
+    bot: Bot,
+    dialogue: MyDialogue,
+    mut user: Profile,
+    q: CallbackQuery,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let group = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(PRICE_GROUP_CALLBACK_PREFIX))
+        .and_then(|g| g.parse::<PriceGroup>().ok());
+    let group = match group {
+        Some(group) => group,
+        None => return Ok(()),
+    };
+
+    if let Some(message) = q.message {
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
+    }
+
+    user.price_group = group;
+    bot.send_message(
+        dialogue.chat_id(),
+        texts.profile_saved(&user.contact, user.price_group),
+    )
+    .await?;
+    dialogue.update(State::Idle { user }).await?;
+    Ok(())
+}
+
+async fn mensa_select_callback(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    mut user: Profile,
+    q: CallbackQuery,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let mensa_id: Option<i32> = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(MENSA_CALLBACK_PREFIX))
+        .and_then(|id| id.parse().ok());
+    let mensa = match mensa_id {
+        Some(id) => client.list_mensas().await?.into_iter().find(|m| m.id == id),
+        None => None,
+    };
+    let mensa = match mensa {
+        Some(mensa) => mensa,
+        None => {
+            bot.send_message(dialogue.chat_id(), texts.mensa_unavailable())
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(message) = q.message {
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
+    }
+
+    user.mensa_id = mensa.id;
+    dialogue.update(State::Idle { user }).await?;
+
+    bot.send_message(
+        dialogue.chat_id(),
+        texts.mensa_selected(&mensa.name, mensa.togo),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn invalid_state(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(msg.from()));
+
+    // Text instead of a button during an order
+    if state.keyboard_message().is_some() {
+        bot.send_message(msg.chat.id, texts.use_buttons()).await?;
+        return Ok(());
+    }
+    if state.profile().is_some() {
+        bot.send_message(msg.chat.id, texts.not_understood())
+            .await?;
+        return Ok(());
+    }
+
+    warn!(
+        "Invalid state callback. message: {:?}, dialogue: {:?}",
+        msg.text(),
+        state
+    );
+    bot.send_message(msg.chat.id, texts.internal_error())
+        .await?;
+    start(bot, dialogue, msg).await?;
+
+    Ok(())
 }
 
-fn make_timeslot_buttons(slots: &LinkedHashMap<String, i32>) -> InlineKeyboardMarkup {
-    let keyboard: Vec<Vec<InlineKeyboardButton>> = slots
-        .iter()
-        .filter(|(_, &nr_free)| nr_free > 0)
-        .map(|(time, nr_free)| {
-            vec![InlineKeyboardButton::callback(
-                format!("{} ({} free)", time, nr_free),
-                time,
-            )]
-        })
-        .collect();
-    InlineKeyboardMarkup::new(keyboard)
+/// A command that is unknown or not available in the current state, instead of taking it as
+/// the answer to the current question
+async fn command_unavailable(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(msg.from()));
+
+    let text = if state.keyboard_message().is_some() {
+        texts.use_buttons()
+    } else if let State::Idle { .. } = state {
+        texts.not_understood()
+    } else {
+        texts.command_unavailable()
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
 }
 
-fn make_menu_buttons(menu: &DayMenu) -> InlineKeyboardMarkup {
-    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
+/// Ends an unfinished order or the setup, keeping the profile
+async fn cancel(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(msg.from()));
 
-    for meal in menu.meals.iter() {
-        let row = vec![InlineKeyboardButton::callback(
-            meal.combined_name.clone(),
-            meal.md5.clone(),
-        )];
+    let text = match state.profile() {
+        None => {
+            dialogue.exit().await?;
+            texts.setup_cancelled()
+        }
+        Some(_) if matches!(state, State::Idle { .. }) => texts.nothing_to_cancel(),
+        Some(_)
+            if matches!(
+                state,
+                State::EditingProfile { .. } | State::VerifyingNewEmail { .. }
+            ) =>
+        {
+            cancel_order(&bot, &dialogue, state).await?;
+            texts.profile_unchanged()
+        }
+        Some(_) => {
+            cancel_order(&bot, &dialogue, state).await?;
+            texts.order_cancelled()
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
 
-        keyboard.push(row);
+/// Cancel button of the order keyboards
+async fn cancel_callback(bot: Bot, dialogue: MyDialogue, q: CallbackQuery) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(Some(&q.from)));
+    if state.keyboard_message().is_some() {
+        cancel_order(&bot, &dialogue, state).await?;
+        bot.send_message(dialogue.chat_id(), texts.order_cancelled())
+            .await?;
+    } else if let Some(message) = q.message {
+        // Keyboard of an order that was already finished
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
     }
+    Ok(())
+}
 
-    InlineKeyboardMarkup::new(keyboard)
+/// Returns to `Idle` and removes the keyboard of an unfinished order.
+async fn cancel_order(bot: &Bot, dialogue: &MyDialogue, state: State) -> HandlerResult {
+    if let Some(user) = state.profile() {
+        dialogue.update(State::Idle { user: user.clone() }).await?;
+    }
+    if let Some(message) = state.keyboard_message() {
+        bot.delete_message(dialogue.chat_id(), message).await?;
+    }
+    Ok(())
 }
 
-async fn help(bot: Bot, msg: Message) -> HandlerResult {
-    bot.send_message(msg.chat.id, Command::descriptions().to_string())
+/// Sends everything stored about the chat as a JSON file.
+async fn my_data(
+    bot: Bot,
+    dialogue: MyDialogue,
+    waitlist: Arc<Waitlist>,
+    subscriptions: Arc<Subscriptions>,
+    alerts: Arc<Alerts>,
+    msg: Message,
+) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(msg.from()));
+    let chat_id = msg.chat.id;
+
+    let data = stored_data(chat_id, &state, &waitlist, &subscriptions, &alerts);
+    let json = serde_json::to_vec_pretty(&data)?;
+    bot.send_document(chat_id, InputFile::memory(json).file_name("mydata.json"))
+        .caption(texts.my_data())
         .await?;
     Ok(())
 }
 
-async fn start(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    let set_cm_f = bot.delete_my_commands().into_future();
-    let sm_f = bot
-        .send_message(msg.chat.id, "Hello! Please enter your first name.")
-        .into_future();
-    let (set_cm_r, sm_r) = join!(set_cm_f, sm_f);
-    set_cm_r?;
-    sm_r?;
-
-    dialogue.update(State::WaitingForFirstName).await?;
-    Ok(())
+/// Everything stored about the chat, for `/mydata`
+fn stored_data(
+    chat_id: ChatId,
+    state: &State,
+    waitlist: &Waitlist,
+    subscriptions: &Subscriptions,
+    alerts: &Alerts,
+) -> serde_json::Value {
+    let sent: Vec<_> = alerts
+        .sent(chat_id)
+        .into_iter()
+        .map(|(date, md5)| serde_json::json!({ "date": date, "md5": md5 }))
+        .collect();
+    serde_json::json!({
+        "chat_id": chat_id,
+        "profile": state.personal_data(),
+        "subscription": subscriptions.get(chat_id),
+        "waitlist": waitlist.entries(chat_id),
+        "alerts": {
+            "keywords": alerts.keywords(chat_id),
+            "announced_meals": sent,
+        },
+    })
 }
 
-async fn receive_first_name(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    bot.send_message(
-        msg.chat.id,
-        format!(
-            "Thanks, {}! Please enter your last name.",
-            msg.text().unwrap()
-        ),
-    )
-    .await?;
-    dialogue
-        .update(State::WaitingForLastName {
-            first_name: msg.text().unwrap().to_owned(),
-        })
+async fn delete_me(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(msg.from()));
+    bot.send_message(msg.chat.id, texts.ask_delete_data())
+        .reply_markup(make_delete_data_buttons(texts))
         .await?;
     Ok(())
 }
 
-async fn receive_last_name(
+/// Confirmation of `/deleteme`: removes the dialogue, waitlist entries, subscription and
+/// alerts of the chat.
+async fn delete_data_callback(
     bot: Bot,
     dialogue: MyDialogue,
-    first_name: String,
-    msg: Message,
+    waitlist: Arc<Waitlist>,
+    subscriptions: Arc<Subscriptions>,
+    alerts: Arc<Alerts>,
+    q: CallbackQuery,
 ) -> HandlerResult {
-    let last_name = msg.text().unwrap();
-    bot.send_message(
-        msg.chat.id,
-        format!(
-            "Thank you, {} {}! Please enter your email address.",
-            first_name, last_name
-        ),
-    )
-    .await?;
-    dialogue
-        .update(State::ReceiveEmail {
-            first_name,
-            last_name: last_name.to_owned(),
-        })
+    let chat_id = dialogue.chat_id();
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(Some(&q.from)));
+
+    dialogue.exit().await?;
+    waitlist.forget_chat(chat_id).await?;
+    subscriptions.unsubscribe(chat_id).await?;
+    alerts.forget_chat(chat_id).await?;
+    log::info!("Deleted all data of {}", chat_id);
+
+    if let Some(message) = q.message {
+        bot.delete_message(chat_id, message.id).await?;
+    }
+    if let Some(message) = state.keyboard_message() {
+        bot.delete_message(chat_id, message).await?;
+    }
+    bot.send_message(chat_id, texts.data_deleted()).await?;
+    Ok(())
+}
+
+async fn keep_data_callback(bot: Bot, dialogue: MyDialogue, q: CallbackQuery) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let texts = Texts::new(state.language(Some(&q.from)));
+    if let Some(message) = q.message {
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
+    }
+    bot.send_message(dialogue.chat_id(), texts.data_kept())
         .await?;
     Ok(())
 }
 
-async fn receive_email(
+/// Deadline of an order step started now, `None` if [`ORDER_TIMEOUT_MINUTES`] is 0
+fn order_deadline() -> Option<DateTime<Utc>> {
+    match ORDER_TIMEOUT_MINUTES.load(Relaxed) {
+        0 => None,
+        minutes => Some(Utc::now() + chrono::Duration::minutes(minutes as i64)),
+    }
+}
+
+/// Cancels the order at `deadline` if it is still waiting for an answer to `message`. The
+/// deadline is also stored in the state, so an order left over from before a restart is
+/// cancelled with the next update, see [`end_expired_order`].
+fn expire_order(
     bot: Bot,
     dialogue: MyDialogue,
-    (first_name, last_name): (String, String),
-    msg: Message,
-) -> HandlerResult {
-    let email = msg.text().unwrap();
-    bot.send_message(
-        msg.chat.id,
-        format!(
-            "This completes the setup! If the following is incorrect, please restart the setup using /start.\nFirst name: \"{}\"\nLast name: \"{}\"\nEmail: \"{}\"",
-            first_name, last_name, email
-        ),
-    )
-    .await?;
-
-    let state_update_f = dialogue
-        .update(State::Idle {
-            user: UserProfile::new(first_name, last_name, email.to_owned()),
-        })
-        .into_future();
+    message: MessageId,
+    deadline: Option<DateTime<Utc>>,
+) {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return,
+    };
 
-    let set_cm_f = bot.set_my_commands(Command::bot_commands()).into_future();
+    tokio::spawn(async move {
+        let remaining = (deadline - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(remaining).await;
 
-    let (su_r, cm_r) = join!(state_update_f, set_cm_f);
-    su_r?;
-    cm_r?;
+        let state = match dialogue.get().await {
+            Ok(Some(state)) if state.keyboard_message() == Some(message) => state,
+            _ => return,
+        };
+        if let Err(e) = end_expired_order(bot, dialogue.clone(), state).await {
+            warn!("Failed to expire order of {}: {}", dialogue.chat_id(), e);
+        }
+    });
+}
 
+/// Cancels an order whose deadline passed, see [`State::is_expired`].
+async fn end_expired_order(bot: Bot, dialogue: MyDialogue, state: State) -> HandlerResult {
+    log::debug!("Order dialogue of {} expired", dialogue.chat_id());
+    let texts = Texts::new(state.language(None));
+    cancel_order(&bot, &dialogue, state).await?;
+    bot.send_message(dialogue.chat_id(), texts.order_expired())
+        .await?;
     Ok(())
 }
 
-async fn invalid_state(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    warn!(
-        "Invalid state callback. message: {:?}, dialogue: {:?}",
-        msg.text(),
-        dialogue.get().await
-    );
-    bot.send_message(
-        msg.chat.id,
-        "Sorry, an error has ocurred in the bot! Restarting dialogue.",
+async fn meal_select_callback(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    (user, date, order_select_message, _expires_at): (
+        Profile,
+        NaiveDate,
+        MessageId,
+        Option<DateTime<Utc>>,
+    ),
+    q: CallbackQuery,
+) -> HandlerResult {
+    let day = (user.mensa_id, date);
+    present_slots(
+        bot,
+        client,
+        dialogue,
+        user,
+        day,
+        q.data.unwrap(),
+        Some(order_select_message),
     )
-    .await?;
-    start(bot, dialogue, msg).await?;
-
-    Ok(())
+    .await
 }
 
-fn any_slots_available(slots: &LinkedHashMap<String, i32>) -> bool {
-    for (_, num_free) in slots {
-        if *num_free > 0 {
-            return true;
+/// Order button of a meal alert
+async fn alert_callback(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    user: Profile,
+    q: CallbackQuery,
+) -> HandlerResult {
+    match q.data.as_deref().and_then(parse_alert) {
+        Some((date, md5)) => {
+            let day = (user.mensa_id, date);
+            present_slots(bot, client, dialogue, user, day, md5, None).await
         }
+        None => Ok(()),
     }
-    false
 }
 
-async fn meal_select_callback(
+/// Lets the user choose a slot for the meal served by the mensa on the day, replacing the
+/// message `replaces` if given.
+async fn present_slots(
     bot: Bot,
+    client: MensaClient,
     dialogue: MyDialogue,
-    (user, iso_date, order_select_message): (UserProfile, String, MessageId),
-    q: CallbackQuery,
+    user: Profile,
+    (mensa_id, date): (i32, NaiveDate),
+    order_md5: String,
+    replaces: Option<MessageId>,
 ) -> HandlerResult {
-    let slots = my_mensa_lib::get_free_slots(2, &user.email, &iso_date).await?;
+    let texts = Texts::new(user.language);
+    let slots = client
+        .get_free_slots(mensa_id, &user.contact.email, date)
+        .await?;
 
-    if !any_slots_available(&slots) {
+    if slots.is_empty() {
         dialogue.update(State::Idle { user }).await?;
-        bot.send_message(dialogue.chat_id(), "No free slots are available!")
+        bot.send_message(dialogue.chat_id(), texts.no_free_slots())
             .await?;
         return Ok(());
     }
 
-    let keyboard = make_timeslot_buttons(&slots);
+    // Fully booked, offer the waitlist instead of the slots
+    let (text, keyboard) = if slots.iter().any(TimeSlot::is_free) {
+        (texts.select_slot(), make_timeslot_buttons(&slots, texts))
+    } else {
+        (texts.waitlist_offer(), make_waitlist_buttons(&slots, texts))
+    };
 
-    let delete_select_f = bot.delete_message(dialogue.chat_id(), order_select_message);
+    let delete_select_f = async {
+        match replaces {
+            Some(message) => bot
+                .delete_message(dialogue.chat_id(), message)
+                .await
+                .map(|_| ()),
+            None => Ok(()),
+        }
+    };
 
     let send_slot_select_f = bot
-        .send_message(dialogue.chat_id(), "Select Time Slot")
+        .send_message(dialogue.chat_id(), text)
         .reply_markup(keyboard);
 
-    let (delete_res, send_res) = join!(
-        delete_select_f.into_future(),
-        send_slot_select_f.into_future()
-    );
+    let (delete_res, send_res) = join!(delete_select_f, send_slot_select_f.into_future());
     delete_res?;
     let slot_select_msg = send_res?;
+    let expires_at = order_deadline();
+    expire_order(bot, dialogue.clone(), slot_select_msg.id, expires_at);
 
     dialogue
         .update(State::WaitingForSlotSelection {
             user,
-            iso_date,
-            order_md5: q.data.unwrap(),
+            date,
+            order_md5,
             slot_select_message: slot_select_msg.id,
+            mensa_id: Some(mensa_id),
+            expires_at,
         })
         .await?;
 
     Ok(())
 }
 
-async fn slot_select_order_callback(
+/// Orders the meal served by the mensa on the day for the slot starting at `slot`, in staging
+/// mode only prepares the order. Returns the confirmation text.
+async fn place_order(
+    client: &MensaClient,
+    user: &Profile,
+    (mensa_id, date): (i32, NaiveDate),
+    md5: &str,
+    slot: NaiveTime,
+    texts: Texts,
+) -> my_mensa_lib::Result<String> {
+    log::debug!("Ordering {:?} in mensa {} for {:?}", md5, mensa_id, user);
+    let prepared = client
+        .prepare_order(date, md5, mensa_id, &user.contact, slot, user.language)
+        .await?;
+
+    if STAGING.load(Relaxed) {
+        log::info!(
+            "STAGING: Not actually ordering anything. Would order: {:?}",
+            prepared
+        );
+        return Ok(texts.ordered_staging(prepared.date, &prepared.slot));
+    }
+
+    let confirmation = client.submit_order(prepared).await?;
+    Ok(texts.confirmation(&confirmation))
+}
+
+async fn slot_select_callback(
     bot: Bot,
+    client: MensaClient,
     dialogue: MyDialogue,
-    (user, iso_date, order_md5, slot_select_message): (UserProfile, String, String, MessageId),
+    (user, date, order_md5, slot_select_message, mensa_id, _expires_at): (
+        Profile,
+        NaiveDate,
+        String,
+        MessageId,
+        Option<i32>,
+        Option<DateTime<Utc>>,
+    ),
     q: CallbackQuery,
 ) -> HandlerResult {
-    bot.send_message(
-        dialogue.chat_id(),
-        format!("Ordering \"{:?}\" for {:?}", order_md5, user),
-    )
-    .await?;
+    let selected_slot = NaiveTime::parse_from_str(&q.data.unwrap(), "%H:%M")?;
 
-    let selected_slot = q.data.unwrap();
-
-    let mensa_id = 2;
+    bot.delete_message(dialogue.chat_id(), slot_select_message)
+        .await?;
+    let day = (mensa_id.unwrap_or(user.mensa_id), date);
+    present_confirmation(bot, client, dialogue, user, day, order_md5, selected_slot).await
+}
 
-    if STAGING.load(Relaxed) {
-        log::info!(
-            "STAGING: Not actually ordering anything. Would order: {:?}, {:?}, {:?}, {:?}, {:?}",
-            iso_date,
-            order_md5,
-            mensa_id,
-            user,
-            selected_slot
-        );
-    } else {
-        my_mensa_lib::order(
-            iso_date.as_str(),
+/// Shows what will be ordered and asks for confirmation. The order is validated first, so
+/// a full slot is reported before the user confirms.
+async fn present_confirmation(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    user: Profile,
+    (mensa_id, date): (i32, NaiveDate),
+    order_md5: String,
+    slot: NaiveTime,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let prepared = match client
+        .prepare_order(
+            date,
             &order_md5,
             mensa_id,
-            &user,
-            &selected_slot,
+            &user.contact,
+            slot,
+            user.language,
         )
+        .await
+    {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            warn!("Order failed: {:?}", e);
+            bot.send_message(dialogue.chat_id(), texts.order_error(&e))
+                .await?;
+            dialogue.update(State::Idle { user }).await?;
+            return Ok(());
+        }
+    };
+
+    // The slot keyboard is gone already, so a failure has to end the order
+    let menu = match client.get_menu(mensa_id, user.language).await {
+        Ok(menu) => menu,
+        Err(e) => {
+            warn!("Failed to get the menu for the order summary: {:?}", e);
+            bot.send_message(dialogue.chat_id(), texts.order_error(&e))
+                .await?;
+            dialogue.update(State::Idle { user }).await?;
+            return Ok(());
+        }
+    };
+    let meal = menu
+        .into_iter()
+        .filter(|day| day.date == date)
+        .flat_map(|day| day.meals)
+        .find(|meal| meal.md5 == order_md5);
+    let (name, price, allergens) = match meal {
+        Some(meal) => (
+            meal.combined_name,
+            meal.prices.get(user.price_group),
+            meal.allergens.into_iter().collect(),
+        ),
+        None => (order_md5.clone(), None, vec![]),
+    };
+
+    let price = price.or(prepared.total);
+    let summary = texts.order_summary(&name, price, date, &prepared.slot, &allergens);
+    let confirm_message = bot
+        .send_message(dialogue.chat_id(), summary)
+        .reply_markup(make_confirm_buttons(texts))
+        .await?;
+    let expires_at = order_deadline();
+    expire_order(bot, dialogue.clone(), confirm_message.id, expires_at);
+
+    dialogue
+        .update(State::WaitingForConfirmation {
+            user,
+            date,
+            order_md5,
+            mensa_id: Some(mensa_id),
+            slot: prepared.slot.start,
+            confirm_message: confirm_message.id,
+            expires_at,
+        })
         .await?;
+
+    Ok(())
+}
+
+async fn confirm_callback(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    (user, date, order_md5, mensa_id, slot, confirm_message, _expires_at): (
+        Profile,
+        NaiveDate,
+        String,
+        Option<i32>,
+        NaiveTime,
+        MessageId,
+        Option<DateTime<Utc>>,
+    ),
+    q: CallbackQuery,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let day = (mensa_id.unwrap_or(user.mensa_id), date);
+    match q.data.as_deref() {
+        Some(CONFIRM_CALLBACK) => {}
+        Some(CHANGE_SLOT_CALLBACK) => {
+            bot.delete_message(dialogue.chat_id(), confirm_message)
+                .await?;
+            return present_slots(bot, client, dialogue, user, day, order_md5, None).await;
+        }
+        _ => return Ok(()),
     }
 
+    bot.send_message(dialogue.chat_id(), texts.ordering())
+        .await?;
+
+    let result = place_order(&client, &user, day, &order_md5, slot, texts).await;
+
+    let result_text = result.unwrap_or_else(|e| {
+        warn!("Order failed: {:?}", e);
+        texts.order_error(&e)
+    });
+
     let delete_f = bot
-        .delete_message(dialogue.chat_id(), slot_select_message)
+        .delete_message(dialogue.chat_id(), confirm_message)
         .into_future();
 
     let res_msg_f = bot
-        .send_message(dialogue.chat_id(), "Ordered!")
+        .send_message(dialogue.chat_id(), result_text)
         .into_future();
 
     let state_update_f = dialogue.update(State::Idle { user });
@@ -339,7 +1691,83 @@ async fn slot_select_order_callback(
     Ok(())
 }
 
-fn select_date<'a>(dates: Vec<&'a str>, explicit_date: Option<&'a str>) -> Option<&'a str> {
+async fn waitlist_callback(
+    bot: Bot,
+    client: MensaClient,
+    waitlist: Arc<Waitlist>,
+    dialogue: MyDialogue,
+    (user, date, order_md5, slot_select_message, mensa_id, _expires_at): (
+        Profile,
+        NaiveDate,
+        String,
+        MessageId,
+        Option<i32>,
+        Option<DateTime<Utc>>,
+    ),
+    q: CallbackQuery,
+) -> HandlerResult {
+    let texts = Texts::new(user.language);
+    let mensa_id = mensa_id.unwrap_or(user.mensa_id);
+    let window = q
+        .data
+        .as_deref()
+        .and_then(|d| d.strip_prefix(WAITLIST_CALLBACK_PREFIX))
+        .and_then(|w| w.split_once('-'))
+        .and_then(|(from, to)| {
+            let from = NaiveTime::parse_from_str(from, "%H:%M").ok()?;
+            let to = NaiveTime::parse_from_str(to, "%H:%M").ok()?;
+            Some((from, to))
+        });
+
+    let meal = client
+        .get_menu(mensa_id, user.language)
+        .await?
+        .into_iter()
+        .filter(|day| day.date == date)
+        .flat_map(|day| day.meals)
+        .find(|meal| meal.md5 == order_md5)
+        .map_or_else(|| order_md5.clone(), |meal| meal.combined_name);
+
+    waitlist
+        .add(WaitlistEntry {
+            chat_id: dialogue.chat_id(),
+            mensa_id,
+            date,
+            md5: order_md5,
+            meal: meal.clone(),
+            window,
+        })
+        .await?;
+
+    bot.delete_message(dialogue.chat_id(), slot_select_message)
+        .await?;
+    bot.send_message(dialogue.chat_id(), texts.waitlist_added(&meal, window))
+        .await?;
+    dialogue.update(State::Idle { user }).await?;
+
+    Ok(())
+}
+
+/// Slot button of a waitlist notification
+async fn freed_slot_callback(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    user: Profile,
+    q: CallbackQuery,
+) -> HandlerResult {
+    let (day, md5, slot) = match q.data.as_deref().and_then(parse_freed_slot) {
+        Some(order) => order,
+        None => return Ok(()),
+    };
+
+    if let Some(message) = q.message {
+        bot.delete_message(dialogue.chat_id(), message.id).await?;
+    }
+    present_confirmation(bot, client, dialogue, user, day, md5, slot).await
+}
+
+fn select_date(dates: Vec<NaiveDate>, explicit_date: Option<NaiveDate>) -> Option<NaiveDate> {
     log::debug!(
         "Selecting date from {:?}, explicit: {:?}",
         dates,
@@ -360,53 +1788,58 @@ fn select_date<'a>(dates: Vec<&'a str>, explicit_date: Option<&'a str>) -> Optio
     let dates: Vec<_> = dates
         .iter()
         // Parse dates, assume 12:00
-        .filter_map(|&s| {
+        .filter_map(|&d| {
             Local
                 .from_local_datetime(&NaiveDateTime::new(
-                    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap(),
+                    d,
                     NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
                 ))
                 .single()
-                .map(|t| (s, t))
+                .map(|t| (d, t))
         })
         .collect();
     log::debug!("Dates: {:?}", dates);
     // Filter dates in the past (current date, if later than 12:00)
-    let future_dates: Vec<(&str, DateTime<Local>)> =
-        dates.into_iter().filter(|(_s, t)| t > &now).collect();
+    let future_dates: Vec<(NaiveDate, DateTime<Local>)> =
+        dates.into_iter().filter(|(_d, t)| t > &now).collect();
     log::debug!("Future dates: {:?}", future_dates);
     // Earliest time
-    let min: Option<(&str, DateTime<Local>)> = future_dates.into_iter().min_by_key(|&(_s, t)| t);
+    let min: Option<(NaiveDate, DateTime<Local>)> =
+        future_dates.into_iter().min_by_key(|&(_d, t)| t);
 
     log::debug!("Min: {:?}", min);
 
-    // Back to string
-    min.map(|(s, _t)| s)
+    min.map(|(d, _t)| d)
 }
 
 async fn present_order(
     bot: Bot,
+    client: MensaClient,
     dialogue: MyDialogue,
-    user: UserProfile,
+    user: Profile,
     msg: Message,
 ) -> HandlerResult {
-    let menu = my_mensa_lib::get_menu(2).await?;
+    let texts = Texts::new(user.language);
+    let menu = client.get_menu(user.mensa_id, user.language).await?;
 
     // Extract explicit date argument, if present
-    let explicit_date = msg
-        .text()
-        .and_then(|text| text.split_once(' '))
-        .map(|(_, date)| date);
-
-    let date = select_date(
-        menu.iter().map(|dm| dm.date.as_str()).collect(),
-        explicit_date,
-    );
+    let explicit_date = match msg.text().and_then(|text| text.split_once(' ')) {
+        Some((_, date)) => match NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => {
+                dialogue.update(State::Idle { user }).await?;
+                bot.send_message(msg.chat.id, texts.invalid_date()).await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let date = select_date(menu.iter().map(|dm| dm.date).collect(), explicit_date);
 
     if date.is_none() {
         dialogue.update(State::Idle { user }).await?;
-        bot.send_message(msg.chat.id, "Error finding correct order date...")
-            .await?;
+        bot.send_message(msg.chat.id, texts.no_order_date()).await?;
         return Ok(());
     }
     let date = date.unwrap();
@@ -414,32 +1847,54 @@ async fn present_order(
     let day_menu = menu.iter().find(|dm| dm.date == date).unwrap();
 
     let m = bot
-        .send_message(msg.chat.id, format!("Choose Meal for {}", date))
-        .reply_markup(make_menu_buttons(day_menu))
+        .send_message(msg.chat.id, texts.choose_meal(date))
+        .reply_markup(make_menu_buttons(day_menu, texts))
         .await?;
+    let expires_at = order_deadline();
+    expire_order(bot, dialogue.clone(), m.id, expires_at);
 
     dialogue
         .update(State::WaitingForOrderSelection {
             user,
-            iso_date: day_menu.date.clone(),
+            date: day_menu.date,
             order_select_message: m.id,
+            expires_at,
         })
         .await?;
 
     Ok(())
 }
 
-async fn menu(bot: Bot, msg: Message) -> HandlerResult {
-    let menu = my_mensa_lib::get_menu(2).await.unwrap();
+async fn menu(
+    bot: Bot,
+    client: MensaClient,
+    dialogue: MyDialogue,
+    msg: Message,
+    diet: String,
+) -> HandlerResult {
+    let state = dialogue.get().await?.unwrap_or_default();
+    let language = state.language(msg.from());
+    let texts = Texts::new(language);
+
+    let diet = match diet.trim() {
+        "" => None,
+        d => match d.parse::<Diet>() {
+            Ok(diet) => Some(diet),
+            Err(_) => {
+                bot.send_message(msg.chat.id, texts.unknown_diet(d)).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let (mensa_id, price_group) = match state.profile() {
+        Some(profile) => (profile.mensa_id, profile.price_group),
+        None => (DEFAULT_MENSA_ID, PriceGroup::default()),
+    };
+    let menu = client.get_menu(mensa_id, language).await?;
     let mut reply = String::new();
     for day in menu {
-        reply += format!("{}:\n", day.date).as_str();
-        for item in day.meals {
-            if item.combined_name.contains("Dessert") || item.combined_name.contains("Beilage") {
-                continue;
-            }
-            reply += format!("  {}\n", item.combined_name).as_str();
-        }
+        reply += &format_day_menu(day, diet, price_group, texts);
     }
 
     bot.send_message(msg.chat.id, reply).await?;
@@ -447,19 +1902,74 @@ async fn menu(bot: Bot, msg: Message) -> HandlerResult {
     Ok(())
 }
 
+/// Main dishes of the day with the prices of `price_group`, optionally only those suitable
+/// for `diet`
+fn format_day_menu(
+    day: DayMenu,
+    diet: Option<Diet>,
+    price_group: PriceGroup,
+    texts: Texts,
+) -> String {
+    let mut reply = format!("{}:\n", texts.date(day.date));
+    for item in day.meals {
+        if !item.is_main_dish() {
+            continue;
+        }
+        if let Some(diet) = diet {
+            if !item.is_suitable_for(diet) {
+                continue;
+            }
+        }
+        match item.prices.get(price_group) {
+            Some(price) => {
+                reply += format!("  {} ({})\n", item.combined_name, format_price(price)).as_str()
+            }
+            None => reply += format!("  {}\n", item.combined_name).as_str(),
+        }
+    }
+    reply
+}
+
 fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     use dptree::case;
 
     let command_handler = teloxide::filter_command::<Command, _>()
         .branch(case![Command::Help].endpoint(help))
         .branch(case![Command::Start].endpoint(start))
-        .branch(case![Command::Menu].endpoint(menu))
-        .branch(case![State::Idle { user }].branch(case![Command::Order].endpoint(present_order)));
+        .branch(case![Command::Menu(diet)].endpoint(menu))
+        .branch(case![Command::Cancel].endpoint(cancel))
+        .branch(case![Command::MyData].endpoint(my_data))
+        .branch(case![Command::DeleteMe].endpoint(delete_me))
+        .branch(
+            case![State::Idle { user }]
+                .branch(case![Command::Order].endpoint(present_order))
+                .branch(case![Command::Mensa].endpoint(choose_mensa))
+                .branch(case![Command::Language(language)].endpoint(set_language))
+                .branch(case![Command::Subscribe(args)].endpoint(subscribe))
+                .branch(case![Command::Unsubscribe].endpoint(unsubscribe))
+                .branch(case![Command::Watch(keyword)].endpoint(watch))
+                .branch(case![Command::Unwatch(keyword)].endpoint(unwatch))
+                .branch(case![Command::Profile].endpoint(show_profile)),
+        );
 
     let message_handler = Update::filter_message()
         .branch(command_handler)
+        .branch(
+            dptree::filter(|msg: Message| msg.text().is_some_and(|t| t.starts_with('/')))
+                .endpoint(command_unavailable),
+        )
         .branch(case![State::WaitingForFirstName].endpoint(receive_first_name))
         .branch(case![State::WaitingForLastName { first_name }].endpoint(receive_last_name))
+        .branch(case![State::EditingProfile { user, field }].endpoint(receive_profile_field))
+        .branch(
+            case![State::VerifyingNewEmail {
+                user,
+                email,
+                code,
+                attempts
+            }]
+            .endpoint(receive_new_email_code),
+        )
         .branch(
             case![State::ReceiveEmail {
                 first_name,
@@ -467,28 +1977,122 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
             }]
             .endpoint(receive_email),
         )
+        .branch(
+            case![State::VerifyingEmail {
+                first_name,
+                last_name,
+                email,
+                code,
+                attempts
+            }]
+            .endpoint(receive_verification_code),
+        )
         .branch(dptree::endpoint(invalid_state));
 
     let callback_query_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.as_deref() == Some(CANCEL_CALLBACK))
+                .endpoint(cancel_callback),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.as_deref() == Some(DELETE_DATA_CALLBACK))
+                .endpoint(delete_data_callback),
+        )
+        .branch(
+            dptree::filter(|q: CallbackQuery| q.data.as_deref() == Some(KEEP_DATA_CALLBACK))
+                .endpoint(keep_data_callback),
+        )
+        .branch(
+            case![State::Idle { user }]
+                .filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(MENSA_CALLBACK_PREFIX))
+                })
+                .endpoint(mensa_select_callback),
+        )
+        .branch(
+            case![State::Idle { user }]
+                .filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(FREED_SLOT_CALLBACK_PREFIX))
+                })
+                .endpoint(freed_slot_callback),
+        )
+        .branch(
+            case![State::Idle { user }]
+                .filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(ALERT_CALLBACK_PREFIX))
+                })
+                .endpoint(alert_callback),
+        )
+        .branch(
+            case![State::Idle { user }]
+                .filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(PROFILE_CALLBACK_PREFIX))
+                })
+                .endpoint(profile_callback),
+        )
+        .branch(
+            case![State::Idle { user }]
+                .filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(PRICE_GROUP_CALLBACK_PREFIX))
+                })
+                .endpoint(price_group_callback),
+        )
         .branch(
             case![State::WaitingForOrderSelection {
                 user,
-                iso_date,
-                order_select_message
+                date,
+                order_select_message,
+                expires_at
             }]
             .endpoint(meal_select_callback),
         )
         .branch(
             case![State::WaitingForSlotSelection {
                 user,
-                iso_date,
+                date,
+                order_md5,
+                slot_select_message,
+                mensa_id,
+                expires_at
+            }]
+            .branch(
+                dptree::filter(|q: CallbackQuery| {
+                    q.data
+                        .as_deref()
+                        .is_some_and(|d| d.starts_with(WAITLIST_CALLBACK_PREFIX))
+                })
+                .endpoint(waitlist_callback),
+            )
+            .endpoint(slot_select_callback),
+        )
+        .branch(
+            case![State::WaitingForConfirmation {
+                user,
+                date,
                 order_md5,
-                slot_select_message
+                mensa_id,
+                slot,
+                confirm_message,
+                expires_at
             }]
-            .endpoint(slot_select_order_callback),
+            .endpoint(confirm_callback),
         );
 
     dialogue::enter::<Update, ErasedStorage<State>, State, _>()
+        .branch(dptree::filter(|state: State| state.is_expired()).endpoint(end_expired_order))
         .branch(message_handler)
         .branch(callback_query_handler)
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,379 @@
+//! Daily menu push messages.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use my_mensa_lib::MensaClient;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use teloxide::prelude::*;
+
+use crate::{format_day_menu, i18n::Texts, stored_profile, MyStorage};
+
+/// Default time of the push message
+const DEFAULT_TIME: (u32, u32) = (11, 0);
+
+const WEEK: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+const GERMAN_WEEKDAYS: [(&str, &str); 7] = [
+    ("mo", "montag"),
+    ("di", "dienstag"),
+    ("mi", "mittwoch"),
+    ("do", "donnerstag"),
+    ("fr", "freitag"),
+    ("sa", "samstag"),
+    ("so", "sonntag"),
+];
+
+/// A chat getting the menu of the day.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub chat_id: ChatId,
+    pub time: NaiveTime,
+    /// Sorted, starting with Monday
+    pub weekdays: Vec<Weekday>,
+}
+
+impl Subscription {
+    /// Date of the last push due after `since` and until `now`, if any
+    fn due_between(&self, since: NaiveDateTime, now: NaiveDateTime) -> Option<NaiveDate> {
+        let mut date = now.date();
+        while date >= since.date() {
+            let push = date.and_time(self.time);
+            if self.weekdays.contains(&date.weekday()) && since < push && push <= now {
+                return Some(date);
+            }
+            date = date.pred_opt()?;
+        }
+        None
+    }
+}
+
+/// Parses the arguments of `/subscribe`: an optional time (`HH:MM`) and optional weekdays,
+/// comma separated days or ranges like `mo-fr`. Defaults to 11:00 from Monday to Friday.
+pub fn parse_schedule(args: &str) -> Option<(NaiveTime, Vec<Weekday>)> {
+    let (hour, min) = DEFAULT_TIME;
+    let mut time = NaiveTime::from_hms_opt(hour, min, 0).unwrap();
+    let mut weekdays = vec![];
+
+    for arg in args.split_whitespace() {
+        if let Ok(t) = NaiveTime::parse_from_str(arg, "%H:%M") {
+            time = t;
+            continue;
+        }
+        for part in arg.split(',').filter(|p| !p.is_empty()) {
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (mut day, to) = (parse_weekday(from)?, parse_weekday(to)?);
+                    weekdays.push(day);
+                    while day != to {
+                        day = day.succ();
+                        weekdays.push(day);
+                    }
+                }
+                None => weekdays.push(parse_weekday(part)?),
+            }
+        }
+    }
+
+    if weekdays.is_empty() {
+        weekdays = WEEK[..5].to_vec();
+    }
+    weekdays.sort_by_key(Weekday::num_days_from_monday);
+    weekdays.dedup();
+
+    Some((time, weekdays))
+}
+
+/// German or English name or abbreviation of a weekday
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    let s = s.trim().to_lowercase();
+    GERMAN_WEEKDAYS
+        .iter()
+        .position(|(short, long)| s == *short || s == *long)
+        .map(|i| WEEK[i])
+        .or_else(|| s.parse().ok())
+}
+
+/// All subscriptions, persisted in the `subscriptions` table if a database is given.
+pub struct Subscriptions {
+    bot: Bot,
+    client: MensaClient,
+    storage: MyStorage,
+    db: Option<SqlitePool>,
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl Subscriptions {
+    /// Loads the subscriptions from `db` and starts sending the menus.
+    pub async fn open(
+        bot: Bot,
+        client: MensaClient,
+        storage: MyStorage,
+        db: Option<SqlitePool>,
+    ) -> Result<Arc<Subscriptions>, sqlx::Error> {
+        let mut subscriptions = vec![];
+        if let Some(db) = &db {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS subscriptions (
+                    chat_id BIGINT PRIMARY KEY,
+                    subscription TEXT NOT NULL
+                )",
+            )
+            .execute(db)
+            .await?;
+
+            let rows: Vec<(String,)> = sqlx::query_as("SELECT subscription FROM subscriptions")
+                .fetch_all(db)
+                .await?;
+            for (subscription,) in rows {
+                match serde_json::from_str(&subscription) {
+                    Ok(subscription) => subscriptions.push(subscription),
+                    Err(e) => log::warn!("Ignoring subscription {:?}: {}", subscription, e),
+                }
+            }
+        }
+
+        let subscriptions = Arc::new(Subscriptions {
+            bot,
+            client,
+            storage,
+            db,
+            subscriptions: Mutex::new(subscriptions),
+        });
+        tokio::spawn(subscriptions.clone().run());
+
+        Ok(subscriptions)
+    }
+
+    /// Adds or replaces the subscription of the chat.
+    pub async fn subscribe(&self, subscription: Subscription) -> Result<(), sqlx::Error> {
+        if let Some(db) = &self.db {
+            sqlx::query("INSERT OR REPLACE INTO subscriptions VALUES (?, ?)")
+                .bind(subscription.chat_id.0)
+                .bind(serde_json::to_string(&subscription).unwrap())
+                .execute(db)
+                .await?;
+        }
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|s| s.chat_id != subscription.chat_id);
+        subscriptions.push(subscription);
+        Ok(())
+    }
+
+    /// Subscription of the chat
+    pub fn get(&self, chat_id: ChatId) -> Option<Subscription> {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.iter().find(|s| s.chat_id == chat_id).cloned()
+    }
+
+    /// Removes the subscription of the chat, returns whether there was one.
+    pub async fn unsubscribe(&self, chat_id: ChatId) -> Result<bool, sqlx::Error> {
+        if let Some(db) = &self.db {
+            sqlx::query("DELETE FROM subscriptions WHERE chat_id = ?")
+                .bind(chat_id.0)
+                .execute(db)
+                .await?;
+        }
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let count = subscriptions.len();
+        subscriptions.retain(|s| s.chat_id != chat_id);
+        Ok(subscriptions.len() < count)
+    }
+
+    /// Checks the subscriptions at the start of every minute. Pushes due since the last check
+    /// are sent as well, so none is lost when a check is late, and all are sent concurrently.
+    async fn run(self: Arc<Self>) {
+        let mut last_run = Local::now().naive_local();
+        loop {
+            tokio::time::sleep(until_next_minute()).await;
+
+            let now = Local::now().naive_local();
+            let due: Vec<(Subscription, NaiveDate)> = self
+                .subscriptions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|s| Some((s.clone(), s.due_between(last_run, now)?)))
+                .collect();
+            last_run = now;
+
+            for (subscription, date) in due {
+                let this = self.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = this.push(&subscription, date).await {
+                        log::warn!("Failed to send menu to {}: {}", subscription.chat_id, e);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Sends the menu of `date`, unless the canteen is closed.
+    async fn push(
+        &self,
+        subscription: &Subscription,
+        date: NaiveDate,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let user = match stored_profile(&self.storage, subscription.chat_id).await {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        let texts = Texts::new(user.language);
+
+        let menu = self.client.get_menu(user.mensa_id, user.language).await?;
+        let day = match menu.into_iter().find(|day| day.date == date) {
+            Some(day) if !day.meals.is_empty() => day,
+            _ => {
+                log::debug!(
+                    "No menu on {}, not sending it to {}",
+                    date,
+                    subscription.chat_id
+                );
+                return Ok(());
+            }
+        };
+
+        self.bot
+            .send_message(
+                subscription.chat_id,
+                format_day_menu(day, None, user.price_group, texts),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn until_next_minute() -> Duration {
+    let now = Local::now();
+    let elapsed = Duration::new(now.second().into(), now.nanosecond().min(999_999_999));
+    Duration::from_secs(60).saturating_sub(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    /// 2023-05-08 is a Monday
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 5, day).unwrap()
+    }
+
+    fn at(day: u32, hour: u32, min: u32, sec: u32) -> NaiveDateTime {
+        date(day).and_hms_opt(hour, min, sec).unwrap()
+    }
+
+    fn subscription(time: NaiveTime, weekdays: &[Weekday]) -> Subscription {
+        Subscription {
+            chat_id: ChatId(1),
+            time,
+            weekdays: weekdays.to_vec(),
+        }
+    }
+
+    #[test]
+    fn schedule_defaults_to_weekdays_at_eleven() {
+        assert_eq!(parse_schedule(""), Some((time(11, 0), WEEK[..5].to_vec())));
+        assert_eq!(
+            parse_schedule("12:30"),
+            Some((time(12, 30), WEEK[..5].to_vec()))
+        );
+    }
+
+    #[test]
+    fn weekdays_and_ranges_are_parsed() {
+        use Weekday::*;
+
+        assert_eq!(
+            parse_schedule("mo,mi Freitag"),
+            Some((time(11, 0), vec![Mon, Wed, Fri]))
+        );
+        assert_eq!(
+            parse_schedule("9:45 di-do"),
+            Some((time(9, 45), vec![Tue, Wed, Thu]))
+        );
+        assert_eq!(
+            parse_schedule("monday,TUE sun"),
+            Some((time(11, 0), vec![Mon, Tue, Sun]))
+        );
+        // Wraps around the weekend, sorted from Monday and without duplicates
+        assert_eq!(
+            parse_schedule("fr-mo mo"),
+            Some((time(11, 0), vec![Mon, Fri, Sat, Sun]))
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for args in ["morgen", "25:00", "mo-", "mo-xy", "11:00 mo,,xy", "12.30"] {
+            assert_eq!(parse_schedule(args), None, "{}", args);
+        }
+    }
+
+    #[test]
+    fn push_is_due_once_at_its_minute() {
+        let s = subscription(time(11, 0), &[Weekday::Mon]);
+
+        assert_eq!(
+            s.due_between(at(8, 10, 59, 0), at(8, 11, 0, 0)),
+            Some(date(8))
+        );
+        assert_eq!(s.due_between(at(8, 11, 0, 0), at(8, 11, 1, 0)), None);
+        assert_eq!(s.due_between(at(8, 10, 58, 0), at(8, 10, 59, 0)), None);
+    }
+
+    #[test]
+    fn late_check_sends_skipped_push() {
+        let s = subscription(time(11, 0), &[Weekday::Mon]);
+
+        assert_eq!(
+            s.due_between(at(8, 10, 59, 0), at(8, 11, 3, 12)),
+            Some(date(8))
+        );
+    }
+
+    #[test]
+    fn push_is_only_due_on_its_weekdays() {
+        let s = subscription(time(11, 0), &[Weekday::Tue]);
+
+        assert_eq!(s.due_between(at(8, 10, 59, 0), at(8, 11, 0, 0)), None);
+        assert_eq!(
+            s.due_between(at(9, 10, 59, 0), at(9, 11, 0, 0)),
+            Some(date(9))
+        );
+    }
+
+    #[test]
+    fn push_before_midnight_is_caught_up_after_it() {
+        let s = subscription(time(23, 59), &[Weekday::Mon]);
+
+        assert_eq!(
+            s.due_between(at(8, 23, 58, 30), at(9, 0, 0, 5)),
+            Some(date(8))
+        );
+    }
+
+    #[test]
+    fn nothing_is_due_when_the_clock_goes_back() {
+        let s = subscription(time(2, 30), &[Weekday::Mon]);
+
+        assert_eq!(s.due_between(at(8, 3, 0, 0), at(8, 2, 31, 0)), None);
+    }
+}
@@ -0,0 +1,457 @@
+//! Drives the dialogue through [`schema`] against a fake Telegram API and the mock my-mensa
+//! server.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+};
+
+use hyper::{
+    body::to_bytes,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use my_mensa_mock::{Fixtures, MockServer};
+use serde_json::{json, Value};
+use teloxide::types::{Me, Update, UpdateKind};
+use tokio::sync::oneshot;
+
+use super::*;
+
+const CHAT_ID: i64 = 4711;
+const SPAETZLE: &str = "4b1f2a6c9d0e8f7a1b2c3d4e5f607182";
+
+#[derive(Default)]
+struct Calls {
+    /// Method name and parameters of every request
+    calls: Vec<(String, Value)>,
+    next_message_id: i32,
+}
+
+/// Telegram Bot API answering every request successfully. Sent messages get increasing ids.
+struct FakeTelegram {
+    addr: SocketAddr,
+    calls: Arc<Mutex<Calls>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl FakeTelegram {
+    async fn start() -> FakeTelegram {
+        let calls = Arc::new(Mutex::new(Calls {
+            calls: vec![],
+            next_message_id: 1,
+        }));
+
+        let service_calls = Arc::clone(&calls);
+        let make_service = make_service_fn(move |_| {
+            let calls = Arc::clone(&service_calls);
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&calls), req))) }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            shutdown_rx.await.ok();
+        }));
+
+        FakeTelegram {
+            addr,
+            calls,
+            _shutdown: shutdown,
+        }
+    }
+
+    fn bot(&self) -> Bot {
+        Bot::new("1:token").set_api_url(format!("http://{}", self.addr).parse().unwrap())
+    }
+
+    /// Texts of all sent messages
+    fn sent_texts(&self) -> Vec<String> {
+        let calls = self.calls.lock().unwrap();
+        calls
+            .calls
+            .iter()
+            .filter(|(method, _)| method == "SendMessage")
+            .filter_map(|(_, params)| params["text"].as_str().map(str::to_owned))
+            .collect()
+    }
+}
+
+async fn handle(
+    calls: Arc<Mutex<Calls>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    // `/bot<token>/<method>`
+    let method = req.uri().path().rsplit('/').next().unwrap_or("").to_owned();
+    let body = to_bytes(req.into_body()).await.unwrap_or_default();
+    let params: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let mut calls = calls.lock().unwrap();
+    let result = if method == "SendMessage" {
+        let message_id = calls.next_message_id;
+        calls.next_message_id += 1;
+        json!({
+            "message_id": message_id,
+            "date": 0,
+            "chat": chat(),
+            "text": params["text"],
+        })
+    } else {
+        json!(true)
+    };
+    calls.calls.push((method, params));
+
+    Ok(Response::new(Body::from(
+        json!({ "ok": true, "result": result }).to_string(),
+    )))
+}
+
+fn profile() -> Profile {
+    Profile {
+        contact: UserProfile::new(
+            "Max".to_owned(),
+            "Mustermann".to_owned(),
+            "max.mustermann@uni-ulm.de".to_owned(),
+        ),
+        mensa_id: 2,
+        language: Language::English,
+        price_group: PriceGroup::Student,
+    }
+}
+
+fn from() -> Value {
+    json!({ "id": CHAT_ID, "is_bot": false, "first_name": "Max", "language_code": "en" })
+}
+
+fn chat() -> Value {
+    json!({ "id": CHAT_ID, "type": "private", "first_name": "Max" })
+}
+
+fn text_update(update_id: i32, text: &str) -> Update {
+    let message = serde_json::from_value(json!({
+        "message_id": 1000 + update_id,
+        "date": 0,
+        "chat": chat(),
+        "from": from(),
+        "text": text,
+    }))
+    .unwrap();
+    Update {
+        id: update_id,
+        kind: UpdateKind::Message(message),
+    }
+}
+
+/// Press of a button with `data` on the message `message_id`
+fn callback_update(update_id: i32, message_id: MessageId, data: &str) -> Update {
+    let query = serde_json::from_value(json!({
+        "id": update_id.to_string(),
+        "from": from(),
+        "chat_instance": "1",
+        "data": data,
+        "message": {
+            "message_id": message_id.0,
+            "date": 0,
+            "chat": chat(),
+            "text": "",
+        },
+    }))
+    .unwrap();
+    Update {
+        id: update_id,
+        kind: UpdateKind::CallbackQuery(query),
+    }
+}
+
+struct Harness {
+    bot: Bot,
+    me: Me,
+    storage: MyStorage,
+    client: MensaClient,
+    waitlist: Arc<Waitlist>,
+    subscriptions: Arc<Subscriptions>,
+    alerts: Arc<Alerts>,
+}
+
+impl Harness {
+    async fn new(telegram: &FakeTelegram, mensa: &MockServer) -> Harness {
+        let bot = telegram.bot();
+        let me = serde_json::from_value(json!({
+            "id": 1,
+            "is_bot": true,
+            "first_name": "Mensa",
+            "username": "uulm_mensa_bot",
+            "can_join_groups": false,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        }))
+        .unwrap();
+        let storage: MyStorage = InMemStorage::new().erase();
+        let client = MensaClient::builder()
+            .api_base_url(mensa.api_base_url())
+            .togo_api_url(mensa.togo_api_url())
+            .build()
+            .unwrap();
+        let waitlist = Waitlist::open(bot.clone(), client.clone(), storage.clone(), None)
+            .await
+            .unwrap();
+        let subscriptions = Subscriptions::open(bot.clone(), client.clone(), storage.clone(), None)
+            .await
+            .unwrap();
+        let alerts = Alerts::open(bot.clone(), client.clone(), storage.clone(), None)
+            .await
+            .unwrap();
+        Harness {
+            bot,
+            me,
+            storage,
+            client,
+            waitlist,
+            subscriptions,
+            alerts,
+        }
+    }
+
+    async fn dispatch(&self, update: Update) {
+        let mailer: Mailer = None;
+        let result = schema()
+            .dispatch(dptree::deps![
+                update,
+                self.bot.clone(),
+                self.me.clone(),
+                self.storage.clone(),
+                self.client.clone(),
+                mailer,
+                self.waitlist.clone(),
+                self.subscriptions.clone(),
+                self.alerts.clone()
+            ])
+            .await;
+        match result {
+            ControlFlow::Break(Ok(())) => {}
+            ControlFlow::Break(Err(e)) => panic!("Handler failed: {}", e),
+            ControlFlow::Continue(_) => panic!("Update was not handled"),
+        }
+    }
+
+    async fn state(&self) -> State {
+        self.storage
+            .clone()
+            .get_dialogue(ChatId(CHAT_ID))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn order_dialogue_places_the_order() {
+    let date = Local::now().date_naive() + chrono::Duration::days(1);
+    let mensa = MockServer::start(Fixtures::synthetic().starting_at(date))
+        .await
+        .unwrap();
+    let telegram = FakeTelegram::start().await;
+    let harness = Harness::new(&telegram, &mensa).await;
+    harness
+        .storage
+        .clone()
+        .update_dialogue(ChatId(CHAT_ID), State::Idle { user: profile() })
+        .await
+        .unwrap();
+
+    harness.dispatch(text_update(1, "/order")).await;
+    let order_select_message = match harness.state().await {
+        State::WaitingForOrderSelection {
+            date: selected,
+            order_select_message,
+            ..
+        } => {
+            assert_eq!(selected, date);
+            order_select_message
+        }
+        state => panic!("Expected the meal selection, got {:?}", state),
+    };
+
+    harness
+        .dispatch(callback_update(2, order_select_message, SPAETZLE))
+        .await;
+    let slot_select_message = match harness.state().await {
+        State::WaitingForSlotSelection {
+            order_md5,
+            slot_select_message,
+            mensa_id,
+            ..
+        } => {
+            assert_eq!(order_md5, SPAETZLE);
+            assert_eq!(mensa_id, Some(2));
+            slot_select_message
+        }
+        state => panic!("Expected the slot selection, got {:?}", state),
+    };
+
+    harness
+        .dispatch(callback_update(3, slot_select_message, "12:15"))
+        .await;
+    let confirm_message = match harness.state().await {
+        State::WaitingForConfirmation {
+            slot,
+            confirm_message,
+            ..
+        } => {
+            assert_eq!(slot, NaiveTime::from_hms_opt(12, 15, 0).unwrap());
+            confirm_message
+        }
+        state => panic!("Expected the confirmation, got {:?}", state),
+    };
+
+    // Only this test places orders, and only against the mock server
+    STAGING.store(false, Relaxed);
+    harness
+        .dispatch(callback_update(4, confirm_message, CONFIRM_CALLBACK))
+        .await;
+
+    assert!(matches!(harness.state().await, State::Idle { .. }));
+    let orders = mensa.orders();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(
+        orders[0].params.get("client[email]").map(String::as_str),
+        Some("max.mustermann@uni-ulm.de")
+    );
+    assert_eq!(mensa.capacity(date, "12:15 - 12:30"), Some(0));
+    let reply = telegram.sent_texts().pop().unwrap();
+    assert!(reply.contains("Order number: 1001"), "{}", reply);
+}
+
+#[tokio::test]
+async fn my_data_leaves_out_verification_codes() {
+    let mensa = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let telegram = FakeTelegram::start().await;
+    let harness = Harness::new(&telegram, &mensa).await;
+    let data = |state: &State| {
+        stored_data(
+            ChatId(CHAT_ID),
+            state,
+            &harness.waitlist,
+            &harness.subscriptions,
+            &harness.alerts,
+        )
+    };
+
+    let setup = data(&State::VerifyingEmail {
+        first_name: "Max".to_owned(),
+        last_name: "Mustermann".to_owned(),
+        email: "max@uni-ulm.de".to_owned(),
+        code: "482913".to_owned(),
+        attempts: 2,
+    });
+    assert_eq!(setup["profile"]["unverified_email"], "max@uni-ulm.de");
+    assert!(!setup.to_string().contains("482913"), "{}", setup);
+    assert!(!setup.to_string().contains("attempts"), "{}", setup);
+
+    let changing_email = data(&State::VerifyingNewEmail {
+        user: profile(),
+        email: "max@example.org".to_owned(),
+        code: "913482".to_owned(),
+        attempts: 0,
+    });
+    assert_eq!(
+        changing_email["profile"]["email"],
+        "max.mustermann@uni-ulm.de"
+    );
+    assert_eq!(
+        changing_email["profile"]["unverified_email"],
+        "max@example.org"
+    );
+    assert!(
+        !changing_email.to_string().contains("913482"),
+        "{}",
+        changing_email
+    );
+}
+
+#[tokio::test]
+async fn unavailable_command_is_not_taken_as_answer() {
+    let mensa = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let telegram = FakeTelegram::start().await;
+    let harness = Harness::new(&telegram, &mensa).await;
+    harness
+        .storage
+        .clone()
+        .update_dialogue(
+            ChatId(CHAT_ID),
+            State::EditingProfile {
+                user: profile(),
+                field: ProfileField::FirstName,
+            },
+        )
+        .await
+        .unwrap();
+
+    harness.dispatch(text_update(1, "/order")).await;
+
+    assert!(matches!(
+        harness.state().await,
+        State::EditingProfile {
+            field: ProfileField::FirstName,
+            ..
+        }
+    ));
+    let reply = telegram.sent_texts().pop().unwrap();
+    assert!(reply.contains("/cancel"), "{}", reply);
+}
+
+#[tokio::test]
+async fn unexpected_text_keeps_the_profile() {
+    let mensa = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let telegram = FakeTelegram::start().await;
+    let harness = Harness::new(&telegram, &mensa).await;
+    harness
+        .storage
+        .clone()
+        .update_dialogue(ChatId(CHAT_ID), State::Idle { user: profile() })
+        .await
+        .unwrap();
+
+    harness.dispatch(text_update(1, "hello")).await;
+    harness.dispatch(text_update(2, "/unknown")).await;
+
+    match harness.state().await {
+        State::Idle { user } => assert_eq!(user.contact.email, "max.mustermann@uni-ulm.de"),
+        state => panic!("Expected the profile to be kept, got {:?}", state),
+    }
+    assert!(telegram
+        .sent_texts()
+        .iter()
+        .all(|text| text.contains("/help")));
+}
+
+#[tokio::test]
+async fn menu_shows_main_dishes_of_the_diet() {
+    let mensa = MockServer::start(Fixtures::synthetic()).await.unwrap();
+    let telegram = FakeTelegram::start().await;
+    let harness = Harness::new(&telegram, &mensa).await;
+    let user = Profile {
+        language: Language::German,
+        ..profile()
+    };
+    harness
+        .storage
+        .clone()
+        .update_dialogue(ChatId(CHAT_ID), State::Idle { user })
+        .await
+        .unwrap();
+
+    harness.dispatch(text_update(1, "/menu")).await;
+    let menu = telegram.sent_texts().pop().unwrap();
+    assert!(menu.contains("Käsespätzle"), "{}", menu);
+    assert!(!menu.contains("Beilagensalat"), "{}", menu);
+    assert!(!menu.contains("Schokoladenpudding"), "{}", menu);
+
+    harness.dispatch(text_update(2, "/menu vegan")).await;
+    let menu = telegram.sent_texts().pop().unwrap();
+    assert!(menu.contains("Gemüsecurry"), "{}", menu);
+    assert!(!menu.contains("Käsespätzle"), "{}", menu);
+}
@@ -0,0 +1,482 @@
+//! Users waiting for a pickup slot of a fully booked day.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use futures_util::StreamExt;
+use my_mensa_lib::{MensaClient, MensaError, SlotChange, TimeSlot};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    RequestError,
+};
+
+use crate::{i18n::Texts, place_order, stored_profile, MyStorage, Profile};
+
+/// Time between two polls of the free slots of a watched day
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Prefix of the callback data of the slot buttons sent when a slot frees up
+pub const FREED_SLOT_CALLBACK_PREFIX: &str = "freed:";
+
+/// A user waiting for a slot to order a meal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    pub chat_id: ChatId,
+    pub mensa_id: i32,
+    pub date: NaiveDate,
+    pub md5: String,
+    /// Name of the meal, for the notification
+    pub meal: String,
+    /// Order the first free slot starting in this window instead of asking
+    pub window: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl WaitlistEntry {
+    fn day(&self) -> (i32, NaiveDate) {
+        (self.mensa_id, self.date)
+    }
+
+    fn is_same(&self, other: &WaitlistEntry) -> bool {
+        self.chat_id == other.chat_id && self.date == other.date && self.md5 == other.md5
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: Vec<WaitlistEntry>,
+    /// Mensa id and date of the days with a running watcher
+    watched: HashSet<(i32, NaiveDate)>,
+}
+
+/// Waiting users, persisted in the `waitlist` table if a database is given. Each waited for
+/// day is polled by a background task until nobody waits for it anymore or the day is over.
+pub struct Waitlist {
+    bot: Bot,
+    client: MensaClient,
+    storage: MyStorage,
+    db: Option<SqlitePool>,
+    entries: Mutex<Entries>,
+}
+
+impl Waitlist {
+    /// Loads the waiting users from `db` and starts watching their days.
+    pub async fn open(
+        bot: Bot,
+        client: MensaClient,
+        storage: MyStorage,
+        db: Option<SqlitePool>,
+    ) -> Result<Arc<Waitlist>, sqlx::Error> {
+        let mut entries = vec![];
+        if let Some(db) = &db {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS waitlist (
+                    chat_id BIGINT NOT NULL,
+                    date TEXT NOT NULL,
+                    md5 TEXT NOT NULL,
+                    entry TEXT NOT NULL,
+                    PRIMARY KEY (chat_id, date, md5)
+                )",
+            )
+            .execute(db)
+            .await?;
+
+            let rows: Vec<(String,)> = sqlx::query_as("SELECT entry FROM waitlist")
+                .fetch_all(db)
+                .await?;
+            for (entry,) in rows {
+                match serde_json::from_str(&entry) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => log::warn!("Ignoring waitlist entry {:?}: {}", entry, e),
+                }
+            }
+        }
+
+        let days: HashSet<_> = entries.iter().map(WaitlistEntry::day).collect();
+        let waitlist = Arc::new(Waitlist {
+            bot,
+            client,
+            storage,
+            db,
+            entries: Mutex::new(Entries {
+                entries,
+                watched: days.clone(),
+            }),
+        });
+        for day in days {
+            tokio::spawn(waitlist.clone().watch(day));
+        }
+
+        Ok(waitlist)
+    }
+
+    /// Adds or replaces the entry of the user for this meal.
+    pub async fn add(self: &Arc<Self>, entry: WaitlistEntry) -> Result<(), sqlx::Error> {
+        if let Some(db) = &self.db {
+            sqlx::query("INSERT OR REPLACE INTO waitlist VALUES (?, ?, ?, ?)")
+                .bind(entry.chat_id.0)
+                .bind(entry.date.to_string())
+                .bind(&entry.md5)
+                .bind(serde_json::to_string(&entry).unwrap())
+                .execute(db)
+                .await?;
+        }
+
+        let day = entry.day();
+        let start_watching = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.entries.retain(|e| !e.is_same(&entry));
+            entries.entries.push(entry);
+            entries.watched.insert(day)
+        };
+        if start_watching {
+            tokio::spawn(self.clone().watch(day));
+        }
+
+        Ok(())
+    }
+
+    /// Entries of the chat
+    pub fn entries(&self, chat_id: ChatId) -> Vec<WaitlistEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .entries
+            .iter()
+            .filter(|e| e.chat_id == chat_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Removes all entries of the chat. Watchers of days nobody else waits for stop after
+    /// their next poll.
+    pub async fn forget_chat(&self, chat_id: ChatId) -> Result<(), sqlx::Error> {
+        if let Some(db) = &self.db {
+            sqlx::query("DELETE FROM waitlist WHERE chat_id = ?")
+                .bind(chat_id.0)
+                .execute(db)
+                .await?;
+        }
+
+        self.entries
+            .lock()
+            .unwrap()
+            .entries
+            .retain(|e| e.chat_id != chat_id);
+        Ok(())
+    }
+
+    async fn remove(&self, entry: &WaitlistEntry) {
+        self.entries
+            .lock()
+            .unwrap()
+            .entries
+            .retain(|e| !e.is_same(entry));
+
+        if let Some(db) = &self.db {
+            let result =
+                sqlx::query("DELETE FROM waitlist WHERE chat_id = ? AND date = ? AND md5 = ?")
+                    .bind(entry.chat_id.0)
+                    .bind(entry.date.to_string())
+                    .bind(&entry.md5)
+                    .execute(db)
+                    .await;
+            if let Err(e) = result {
+                log::warn!("Failed to remove waitlist entry {:?}: {}", entry, e);
+            }
+        }
+    }
+
+    fn waiting_for(&self, day: (i32, NaiveDate)) -> Vec<WaitlistEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .entries
+            .iter()
+            .filter(|e| e.day() == day)
+            .cloned()
+            .collect()
+    }
+
+    /// Marks the day as no longer watched if nobody waits for it. Checked together with
+    /// [`Waitlist::add`], so no entry is left without a watcher.
+    fn stop_if_unused(&self, day: (i32, NaiveDate)) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.entries.iter().any(|e| e.day() == day) {
+            return false;
+        }
+        entries.watched.remove(&day);
+        true
+    }
+
+    async fn watch(self: Arc<Self>, day: (i32, NaiveDate)) {
+        let (mensa_id, date) = day;
+        log::debug!("Watching free slots of mensa {} on {}", mensa_id, date);
+
+        // The slots are polled as seen by the first waiting user, each user's own view is
+        // fetched again once a slot opens
+        let email = match self.waiting_for(day).first() {
+            Some(entry) => stored_profile(&self.storage, entry.chat_id)
+                .await
+                .map(|user| user.contact.email)
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+        let day_over = tokio::time::sleep(until_end_of(date));
+        let changes = self
+            .client
+            .watch_slots(mensa_id, &email, date, POLL_INTERVAL);
+        tokio::pin!(day_over, changes);
+
+        loop {
+            tokio::select! {
+                _ = &mut day_over => break,
+                change = changes.next() => {
+                    if let Some(Ok(SlotChange::Opened(_))) = change {
+                        self.slots_opened(day).await;
+                    }
+                    if self.stop_if_unused(day) {
+                        return;
+                    }
+                }
+            }
+        }
+
+        let expired: Vec<WaitlistEntry> = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.watched.remove(&day);
+            let (expired, waiting) = entries.entries.drain(..).partition(|e| e.day() == day);
+            entries.entries = waiting;
+            expired
+        };
+        for entry in expired {
+            self.remove(&entry).await;
+            if let Some(user) = stored_profile(&self.storage, entry.chat_id).await {
+                let texts = Texts::new(user.language);
+                let text = texts.waitlist_expired(&entry.meal, entry.date);
+                if let Err(e) = self.bot.send_message(entry.chat_id, text).await {
+                    log::warn!("Failed to notify {}: {}", entry.chat_id, e);
+                }
+            }
+        }
+    }
+
+    async fn slots_opened(&self, day: (i32, NaiveDate)) {
+        let (mensa_id, date) = day;
+        for entry in self.waiting_for(day) {
+            let user = match stored_profile(&self.storage, entry.chat_id).await {
+                Some(user) => user,
+                None => {
+                    self.remove(&entry).await;
+                    continue;
+                }
+            };
+            // The slots offered depend on the email of the user
+            let free: Vec<TimeSlot> = match self
+                .client
+                .get_free_slots(mensa_id, &user.contact.email, date)
+                .await
+            {
+                Ok(slots) => slots.into_iter().filter(TimeSlot::is_free).collect(),
+                Err(e) => {
+                    log::warn!("Failed to get free slots of mensa {}: {}", mensa_id, e);
+                    continue;
+                }
+            };
+            if free.is_empty() {
+                continue;
+            }
+
+            match self.notify(&entry, &user, &free).await {
+                Ok(false) => {}
+                Ok(true) => self.remove(&entry).await,
+                Err(e) => {
+                    log::warn!("Failed to notify {}: {}", entry.chat_id, e);
+                    self.remove(&entry).await;
+                }
+            }
+        }
+    }
+
+    /// Offers the free slots to the user, or orders one in the window. Returns whether the
+    /// user is done waiting.
+    async fn notify(
+        &self,
+        entry: &WaitlistEntry,
+        user: &Profile,
+        free: &[TimeSlot],
+    ) -> Result<bool, RequestError> {
+        let texts = Texts::new(user.language);
+
+        let (from, to) = match entry.window {
+            Some(window) => window,
+            None => {
+                self.bot
+                    .send_message(entry.chat_id, texts.slot_freed(&entry.meal, entry.date))
+                    .reply_markup(make_freed_slot_buttons(entry, free, texts))
+                    .await?;
+                return Ok(true);
+            }
+        };
+
+        let slot = match slot_in_window(free, (from, to)) {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        let result = match place_order(
+            &self.client,
+            user,
+            entry.day(),
+            &entry.md5,
+            slot.start,
+            texts,
+        )
+        .await
+        {
+            Err(MensaError::SlotFull { .. }) => return Ok(false),
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Automatic order failed: {:?}", e);
+                texts.order_error(&e)
+            }
+        };
+        self.bot
+            .send_message(
+                entry.chat_id,
+                format!("{}\n{}", texts.slot_freed_auto(&entry.meal), result),
+            )
+            .await?;
+        Ok(true)
+    }
+}
+
+/// First free slot starting in the window, including its start and excluding its end
+fn slot_in_window(free: &[TimeSlot], (from, to): (NaiveTime, NaiveTime)) -> Option<&TimeSlot> {
+    free.iter()
+        .find(|s| s.is_free() && s.start >= from && s.start < to)
+}
+
+fn make_freed_slot_buttons(
+    entry: &WaitlistEntry,
+    free: &[TimeSlot],
+    texts: Texts,
+) -> InlineKeyboardMarkup {
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = free
+        .iter()
+        .map(|slot| {
+            vec![InlineKeyboardButton::callback(
+                texts.slot_button(slot),
+                format!(
+                    "{}{}:{}:{}:{}",
+                    FREED_SLOT_CALLBACK_PREFIX,
+                    entry.mensa_id,
+                    entry.date,
+                    entry.md5,
+                    slot.start.format("%H:%M")
+                ),
+            )]
+        })
+        .collect();
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Mensa id and date, meal md5 and slot start of a freed slot button
+pub fn parse_freed_slot(data: &str) -> Option<((i32, NaiveDate), String, NaiveTime)> {
+    let mut parts = data
+        .strip_prefix(FREED_SLOT_CALLBACK_PREFIX)?
+        .splitn(4, ':');
+    let mensa_id = parts.next()?.parse().ok()?;
+    let date = parts.next()?.parse().ok()?;
+    let md5 = parts.next()?.to_owned();
+    let slot = NaiveTime::parse_from_str(parts.next()?, "%H:%M").ok()?;
+    Some(((mensa_id, date), md5, slot))
+}
+
+/// Time until midnight after `date`
+fn until_end_of(date: NaiveDate) -> Duration {
+    let end = date
+        .succ_opt()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|t| Local.from_local_datetime(&t).earliest());
+    match end {
+        Some(end) => (end - Local::now()).to_std().unwrap_or_default(),
+        None => Duration::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn slot(h: u32, m: u32, capacity: i32) -> TimeSlot {
+        TimeSlot {
+            start: time(h, m),
+            end: time(h, m) + chrono::Duration::minutes(15),
+            capacity,
+        }
+    }
+
+    fn entry() -> WaitlistEntry {
+        WaitlistEntry {
+            chat_id: ChatId(42),
+            mensa_id: 7,
+            date: NaiveDate::from_ymd_opt(2023, 5, 8).unwrap(),
+            md5: "0123456789abcdef0123456789abcdef".to_owned(),
+            meal: "Linsen".to_owned(),
+            window: None,
+        }
+    }
+
+    #[test]
+    fn freed_slot_buttons_are_parsed() {
+        let entry = entry();
+        let buttons =
+            make_freed_slot_buttons(&entry, &[slot(11, 30, 2)], Texts::new(Default::default()));
+        let data = match &buttons.inline_keyboard[0][0].kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("Unexpected button {:?}", kind),
+        };
+        assert!(data.len() <= 64, "Callback data too long: {}", data);
+
+        let (day, md5, start) = parse_freed_slot(&data).unwrap();
+        assert_eq!(day, (7, entry.date));
+        assert_eq!(md5, entry.md5);
+        assert_eq!(start, time(11, 30));
+    }
+
+    #[test]
+    fn invalid_freed_slots_are_ignored() {
+        assert!(parse_freed_slot("freed:2023-05-08:abc:11:30").is_none());
+        assert!(parse_freed_slot("freed:7:2023-05-08:abc").is_none());
+        assert!(parse_freed_slot("alert:7:2023-05-08:abc:11:30").is_none());
+        assert!(parse_freed_slot("freed:7:2023-05-08:abc:noon").is_none());
+    }
+
+    #[test]
+    fn slot_in_window_is_first_free_start() {
+        let free = [
+            slot(11, 15, 1),
+            slot(11, 30, 0),
+            slot(11, 45, 3),
+            slot(12, 0, 1),
+        ];
+
+        let window = (time(11, 30), time(12, 0));
+        assert_eq!(slot_in_window(&free, window), Some(&free[2]));
+        // The end of the window is exclusive
+        let window = (time(12, 0), time(12, 30));
+        assert_eq!(slot_in_window(&free, window), Some(&free[3]));
+        let window = (time(11, 30), time(11, 45));
+        assert_eq!(slot_in_window(&free, window), None);
+        let window = (time(12, 15), time(12, 45));
+        assert_eq!(slot_in_window(&free, window), None);
+    }
+}
@@ -1,11 +1,24 @@
-use my_mensa_lib::{get_free_slots, get_menu, order, UserProfile};
+use my_mensa_lib::{
+    format_price, Allergen, Basket, Diet, Language, MensaClient, MensaError, Prices, UserProfile,
+    DEFAULT_API_BASE_URL, DEFAULT_MENSA_ID, DEFAULT_TOGO_API_URL,
+};
 
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use chrono::{Local, NaiveDate, NaiveTime};
 use clap::{Parser, Subcommand};
+use futures_util::{pin_mut, StreamExt};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    mensa_id: i32,
+    /// Id or name of the mensa, e.g. `west`. Defaults to Mensa West
+    #[arg(short, long, global = true)]
+    mensa: Option<String>,
+
+    /// Language of the menu (de, en)
+    #[arg(short, long, global = true, default_value_t = Language::German)]
+    lang: Language,
 
     #[command(subcommand)]
     command: Commands,
@@ -13,19 +26,80 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Menu {},
+    /// List all canteens and their ids
+    Mensas,
+    Menu {
+        /// Hide meals containing any of these allergens (codes or names, comma separated)
+        #[arg(long, value_delimiter = ',')]
+        without: Vec<Allergen>,
+        /// Only show meals suitable for this diet (vegan, vegetarian, pescetarian)
+        #[arg(long)]
+        diet: Option<Diet>,
+    },
     Slots {
         email: String,
-        iso_date: String,
+        date: NaiveDate,
+        /// Keep polling and print every change of the free slots
+        #[arg(long)]
+        watch: bool,
+        /// Seconds between two polls in watch mode, at least 5
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(5..))]
+        interval: u64,
     },
     Order {
-        iso_date: String,
-        meal_md5: String,
-        time: String,
+        date: NaiveDate,
+        /// Meals to order as md5, optionally with quantity (md5:2, at most 10), comma separated
+        #[arg(value_parser = parse_positions)]
+        meals: Positions,
+        /// Start of the pickup slot (HH:MM)
+        #[arg(value_parser = parse_time)]
+        time: NaiveTime,
         firstname: String,
         lastname: String,
         email: String,
+        /// Validate the order and print the request instead of submitting it
+        #[arg(long)]
+        dry_run: bool,
     },
+    /// Record the menus and free slots of the server as fixtures for my-mensa-mock
+    ///
+    /// Saves the German and English menu and the free slots unchanged. Nothing is ordered.
+    RecordFixtures {
+        /// Directory the fixtures are written to
+        dir: PathBuf,
+        /// Email the free slots are requested for
+        email: String,
+        /// Day of the free slots, defaults to the first day of the menu
+        date: Option<NaiveDate>,
+    },
+}
+
+/// md5 and quantity of each meal. An alias, so clap treats it as a single value instead of a
+/// list of positional arguments.
+type Positions = Vec<(String, u32)>;
+
+/// Comma separated list of `md5` or `md5:quantity`
+fn parse_positions(s: &str) -> Result<Positions, String> {
+    s.split(',')
+        .map(|p| match p.split_once(':') {
+            Some((md5, quantity)) => quantity
+                .parse()
+                .map(|q| (md5.to_owned(), q))
+                .map_err(|e| format!("Invalid quantity {:?}: {}", quantity, e)),
+            None => Ok((p.to_owned(), 1)),
+        })
+        .collect()
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, chrono::ParseError> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+}
+
+/// Student / staff / guest prices
+fn format_prices(prices: &Prices) -> String {
+    [prices.student, prices.staff, prices.guest]
+        .map(|p| p.map(format_price).unwrap_or_else(|| "-".to_owned()))
+        .join(" / ")
 }
 
 #[tokio::main]
@@ -34,43 +108,233 @@ async fn main() {
 
     let cli = Cli::parse();
 
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        if let Some(
+            MensaError::Decode { body_snippet, .. }
+            | MensaError::ServerUnavailable { body_snippet, .. },
+        ) = e.downcast_ref()
+        {
+            eprintln!("Response: {}", body_snippet);
+        }
+        if let Some(MensaError::OrderUnconfirmed { .. }) = e.downcast_ref() {
+            eprintln!("Check your email for an order confirmation before ordering again.");
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Resolves the `--mensa` argument to an id
+async fn mensa_id(client: &MensaClient, mensa: Option<&str>) -> Result<i32, MensaError> {
+    match mensa {
+        None => Ok(DEFAULT_MENSA_ID),
+        Some(m) => match m.parse() {
+            Ok(id) => Ok(id),
+            Err(_) => Ok(client.find_mensa(m).await?.id),
+        },
+    }
+}
+
+/// Saves the responses of `getdata.php` in German and English and of `get_free_slots` in
+/// `dir` unchanged, in the layout read by `my-mensa-mock --fixtures`. Fails if one is not JSON.
+async fn record_fixtures(
+    mensa_id: i32,
+    dir: PathBuf,
+    email: &str,
+    date: Option<NaiveDate>,
+) -> Result<(), Box<dyn Error>> {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    std::fs::create_dir_all(&dir)?;
+
+    let mut first_day = None;
+    for (lang, file) in [
+        (Language::German, "getdata.json"),
+        (Language::English, "getdata_en.json"),
+    ] {
+        let url = format!(
+            "{}/getdata.php?mensa_id={}&json=1&hyp=1&mode=togo&lang={}",
+            DEFAULT_API_BASE_URL,
+            mensa_id,
+            lang.code()
+        );
+        let body = http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let json: serde_json::Value = serde_json::from_str(&body)?;
+        first_day = first_day.or_else(|| {
+            json["result"][0]["tag"]["datum_iso"]
+                .as_str()
+                .and_then(|d| d.parse::<NaiveDate>().ok())
+        });
+        std::fs::write(dir.join(file), body)?;
+        println!("Saved {}", file);
+    }
+
+    let date = date.or(first_day).ok_or("The menu has no days")?;
+    let body = http
+        .post(format!("{}/get_free_slots/", DEFAULT_TOGO_API_URL))
+        .form(&[
+            ("mensa_id", mensa_id.to_string()),
+            ("tag", date.format("%Y-%m-%d").to_string()),
+            ("id", email.to_owned()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    serde_json::from_str::<serde_json::Value>(&body)?;
+    std::fs::write(dir.join("free_slots.json"), body)?;
+    println!("Saved free_slots.json for {}", date);
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let client = MensaClient::new()?;
+    let mensa = cli.mensa.as_deref();
+
     match cli.command {
-        Commands::Menu {} => {
-            let menu = get_menu(2).await.unwrap();
+        Commands::Mensas => {
+            for mensa in client.list_mensas().await? {
+                print!("{:>3}: {}", mensa.id, mensa.name);
+                if let Some(location) = &mensa.location {
+                    print!(", {}", location);
+                }
+                println!("{}", if mensa.togo { " (to-go)" } else { "" });
+            }
+        }
+        Commands::Menu { without, diet } => {
+            let mensa_id = mensa_id(&client, mensa).await?;
+            let menu = client.get_menu(mensa_id, cli.lang).await?;
             for day in menu {
                 println!("{}:", day.date);
                 for item in day.meals {
-                    println!("  {} ({})", item.combined_name, item.md5);
+                    if without.iter().any(|a| item.allergens.contains(a)) {
+                        continue;
+                    }
+                    if let Some(diet) = diet {
+                        if !item.is_suitable_for(diet) {
+                            continue;
+                        }
+                    }
+                    println!(
+                        "  {} [{}] ({})",
+                        item.combined_name,
+                        format_prices(&item.prices),
+                        item.md5
+                    );
+                    if !item.allergens.is_empty() {
+                        let allergens: Vec<_> =
+                            item.allergens.iter().map(|a| a.name_en()).collect();
+                        println!("    Allergens: {}", allergens.join(", "));
+                    }
                 }
             }
         }
-        Commands::Slots { email, iso_date } => {
-            let slots = get_free_slots(cli.mensa_id, email.as_str(), iso_date.as_str())
-                .await
-                .unwrap();
-            println!("Free slots for {}:", iso_date);
-            for (time, count) in slots {
-                println!("  {}: {}", time, count);
+        Commands::Slots {
+            email,
+            date,
+            watch,
+            interval,
+        } => {
+            let mensa_id = mensa_id(&client, mensa).await?;
+            if watch {
+                println!("Watching free slots for {}, press Ctrl-C to stop", date);
+                let changes =
+                    client.watch_slots(mensa_id, &email, date, Duration::from_secs(interval));
+                pin_mut!(changes);
+                while let Some(change) = changes.next().await {
+                    let now = Local::now().format("%H:%M:%S");
+                    match change {
+                        Ok(change) => println!("{} {}", now, change),
+                        Err(e) => eprintln!("{} Error: {}", now, e),
+                    }
+                }
+                return Ok(());
+            }
+            let slots = client
+                .get_free_slots(mensa_id, email.as_str(), date)
+                .await?;
+            println!("Free slots for {}:", date);
+            for slot in slots {
+                println!("  {}: {}", slot, slot.capacity);
             }
         }
         Commands::Order {
-            iso_date,
-            meal_md5,
+            date,
+            meals,
             time,
             firstname,
             lastname,
             email,
+            dry_run,
         } => {
-            let res = order(
-                &iso_date,
-                &meal_md5,
-                cli.mensa_id,
-                &UserProfile::new(firstname, lastname, email),
-                &time,
-            )
-            .await
-            .unwrap();
-            println!("{}", res);
+            let mensa_id = mensa_id(&client, mensa).await?;
+            let menu = client.get_menu(mensa_id, cli.lang).await?;
+            let day = menu
+                .into_iter()
+                .find(|d| d.date == date)
+                .ok_or(MensaError::DayNotFound { date })?;
+
+            let mut basket = Basket::new(date);
+            for (md5, quantity) in meals {
+                let item = day
+                    .meals
+                    .iter()
+                    .find(|m| m.md5 == md5)
+                    .ok_or(MensaError::MealNotFound { md5 })?;
+                basket.add(item, quantity)?;
+            }
+
+            for i in basket.items() {
+                println!("{}x {}", i.quantity, i.item.name);
+            }
+            if let Some(total) = basket.total() {
+                println!("Total: {}", format_price(total));
+            }
+
+            let prepared = client
+                .prepare_basket_order(
+                    mensa_id,
+                    &basket,
+                    &UserProfile::new(firstname, lastname, email),
+                    time,
+                    cli.lang,
+                )
+                .await?;
+
+            if dry_run {
+                println!("Pickup: {} {}", prepared.date, prepared.slot);
+                println!("Request parameters:");
+                for (key, value) in &prepared.params {
+                    println!("  {} = {}", key, value);
+                }
+                return Ok(());
+            }
+
+            let confirmation = client.submit_order(prepared).await?;
+            println!(
+                "Order confirmed for {} {}",
+                confirmation.date, confirmation.slot
+            );
+            if let Some(number) = confirmation.order_number {
+                println!("Order number: {}", number);
+            }
+            if let Some(status) = confirmation.status {
+                println!("Status: {}", status);
+            }
+        }
+        Commands::RecordFixtures { dir, email, date } => {
+            let mensa_id = mensa_id(&client, mensa).await?;
+            record_fixtures(mensa_id, dir, &email, date).await?;
         }
     }
+
+    Ok(())
 }